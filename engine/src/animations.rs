@@ -1,15 +1,8 @@
-use crate::sprite::{Action, Character};
+use crate::sprite::{Action, Character, Sprite};
 use crate::types::{Image, Rect, Vec2i};
 use std::collections::hash_map::HashMap;
 use std::rc::Rc;
 
-const MARIO_RECT_WIDTH: usize = 20;
-const MARIO_RECT_HEIGHT: usize = 32;
-const INVADER_ENEMY_RECT_WIDTH: usize = 16;
-const INVADER_ENEMY_RECT_HEIGHT: usize = 16;
-const INVADER_PLAYER_RECT_WIDTH: usize = 22;
-const INVADER_PLAYER_RECT_HEIGHT: usize = 16;
-
 #[allow(dead_code)]
 #[derive(PartialEq, Clone, Debug)]
 pub struct Animation {
@@ -24,17 +17,34 @@ impl Animation {
     // Be used to decide what frame to use...
     // Could have a query function like current_frame(&self, start_time:usize, now:usize, speedup_factor:usize)
     // Or could be ticked in-place with a function like tick(&self)
+    /// `count` frames of size `sz` laid out left to right starting at `pos`
+    pub fn strip(pos: Vec2i, sz: Vec2i, count: usize, loops: bool) -> Self {
+        Self {
+            frames: (0..count as i32)
+                .map(|i| Rect {
+                    pos: Vec2i {
+                        x: pos.x + i * sz.x,
+                        y: pos.y,
+                    },
+                    sz,
+                })
+                .collect(),
+            frame_timings: (0..count).map(|i| i * 10).collect(),
+            loops,
+        }
+    }
+
     pub fn initial_frame(&self) -> Rect {
         self.frames[0]
     }
 
     pub fn current_frame(&self, start_time: usize, now: usize, speedup_factor: &usize) -> Rect {
         let frame_timing = (now - start_time) / speedup_factor;
-        self.frames[frame_timing]
+        // Hold on the last frame once a one-shot animation has run out
+        self.frames[frame_timing.min(self.frames.len() - 1)]
     }
 
-    #[allow(dead_code)]
-    fn is_finished(&self, start_time: usize, now: usize, speedup_factor: &usize) -> bool {
+    pub fn is_finished(&self, start_time: usize, now: usize, speedup_factor: &usize) -> bool {
         // return true if the end time of this animation is passed.
         (now - start_time) / speedup_factor >= self.frames.len()
     }
@@ -51,42 +61,176 @@ pub struct AnimationState {
     pub now: usize,
     pub action: Action,
     pub animation: Rc<Animation>,
+    // Whether this playback wraps around; starts out as animation.loops
+    pub loops: bool,
+    // Ticks since this animation was started, not reset when looping
+    pub elapsed: usize,
 }
 
 impl AnimationState {
-    #[allow(dead_code)]
     pub fn tick(&mut self, speedup_factor: &usize) -> Rect {
         self.now += 1;
-        if self
-            .animation
-            .is_finished(self.start_time, self.now, speedup_factor)
-            && self.animation.loops
+        self.elapsed += 1;
+        if self.loops
+            && self
+                .animation
+                .is_finished(self.start_time, self.now, speedup_factor)
         {
-            self.now = 0;
+            self.now = self.start_time;
         }
+        self.current_frame(speedup_factor)
+    }
+
+    pub fn current_frame(&self, speedup_factor: &usize) -> Rect {
         self.animation
             .current_frame(self.start_time, self.now, speedup_factor)
     }
+
+    /// A looping animation never finishes; a one-shot one finishes after its last frame
+    pub fn is_finished(&self, speedup_factor: &usize) -> bool {
+        !self.loops
+            && self
+                .animation
+                .is_finished(self.start_time, self.now, speedup_factor)
+    }
+
+    /// Whether every frame has been shown at least once, looping or not
+    pub fn played_through(&self, speedup_factor: &usize) -> bool {
+        self.elapsed / speedup_factor >= self.animation.frames.len()
+    }
+}
+
+/// Values the game feeds the state machine each tick
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct AnimParams {
+    pub speed: f32,
+    pub grounded: bool,
+    pub facing_left: bool,
+}
+
+/// A guard on a transition; all of a transition's conditions must hold
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Condition {
+    SpeedAtLeast(f32),
+    SpeedBelow(f32),
+    Grounded(bool),
+    FacingLeft(bool),
+}
+
+impl Condition {
+    pub fn holds(&self, params: &AnimParams) -> bool {
+        match *self {
+            Condition::SpeedAtLeast(s) => params.speed >= s,
+            Condition::SpeedBelow(s) => params.speed < s,
+            Condition::Grounded(g) => params.grounded == g,
+            Condition::FacingLeft(f) => params.facing_left == f,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Transition {
+    // None means "from any state"
+    pub from: Option<Action>,
+    pub to: Action,
+    pub when: Vec<Condition>,
+    // Wait for the current animation to play through once before leaving it
+    pub exit_time: bool,
+}
+
+impl Transition {
+    pub fn new(from: Option<Action>, to: Action, when: Vec<Condition>) -> Self {
+        Self {
+            from,
+            to,
+            when,
+            exit_time: false,
+        }
+    }
+
+    pub fn with_exit_time(self) -> Self {
+        Self {
+            exit_time: true,
+            ..self
+        }
+    }
+}
+
+/// Picks a sprite's animation from a table of transitions instead of ad hoc game code.
+/// Transitions are tried in order and the first one that applies wins.
+#[derive(PartialEq, Clone, Debug)]
+pub struct AnimStateMachine {
+    pub transitions: Vec<Transition>,
+    pub params: AnimParams,
+}
+
+impl AnimStateMachine {
+    pub fn new(transitions: Vec<Transition>) -> Self {
+        Self {
+            transitions,
+            params: AnimParams::default(),
+        }
+    }
+
+    /// Which action the machine would switch `current` to, if any
+    pub fn next_action(&self, current: &AnimationState, speedup_factor: &usize) -> Option<Action> {
+        self.transitions
+            .iter()
+            .find(|t| {
+                t.to != current.action
+                    && t.from.unwrap_or(current.action) == current.action
+                    && (!t.exit_time || current.played_through(speedup_factor))
+                    && t.when.iter().all(|c| c.holds(&self.params))
+            })
+            .map(|t| t.to)
+    }
+
+    /// Apply any transition that fires, then advance the sprite's animation by one tick
+    pub fn tick(&self, set: &AnimationSet, sprite: &mut Sprite, speedup_factor: &usize) -> Rect {
+        if let Some(action) = self.next_action(&sprite.animation_state, speedup_factor) {
+            sprite.set_animation(set.play_animation(action));
+        }
+        sprite.play_animation(speedup_factor)
+    }
 }
 
 pub struct AnimationSet {
     pub character: Character, // dont need this
-    pub image: Image,
+    pub image: Rc<Image>,
     pub animations: HashMap<Action, Rc<Animation>>,
+    pub transitions: Vec<Transition>,
 }
 
 impl AnimationSet {
     pub fn get_animation(&self, action: Action) -> &Rc<Animation> {
         // let this return an AnimationState, clone
-        self.animations.get(&action).unwrap()
+        self.animations
+            .get(&action)
+            .unwrap_or_else(|| panic!("{} has no animation for action {}", self.character, action))
     }
 
     pub fn play_animation(&self, action: Action) -> AnimationState {
+        let animation = self.get_animation(action).clone();
         AnimationState {
             start_time: 0,
             now: 0,
-            action: action,
-            animation: self.animations.get(&action).unwrap().clone(),
+            action,
+            loops: animation.loops,
+            elapsed: 0,
+            animation,
+        }
+    }
+
+    /// A fresh state machine driven by this character's transition table
+    pub fn state_machine(&self) -> AnimStateMachine {
+        AnimStateMachine::new(self.transitions.clone())
+    }
+
+    /// Play an animation through a single time, even if it normally loops
+    pub fn play_animation_once(&self, action: Action) -> AnimationState {
+        AnimationState {
+            loops: false,
+            ..self.play_animation(action)
         }
     }
 
@@ -102,7 +246,11 @@ impl AnimationSet {
         self.animations = animations;
     }
 
-    pub fn set_image(&mut self, image: Image) {
+    pub fn add_animation(&mut self, action: Action, animation: Animation) {
+        self.animations.insert(action, Rc::new(animation));
+    }
+
+    pub fn set_image(&mut self, image: Rc<Image>) {
         self.image = image;
     }
 
@@ -110,436 +258,169 @@ impl AnimationSet {
         self.character = character;
     }
 
-    pub fn new(character: Character) -> Self {
-        let image = Image::from_file(std::path::Path::new("../hoophorse/content/spritesheet.png"));
-        let mut animations: HashMap<Action, Rc<Animation>> = HashMap::new();
-        if character == Character::Mario {
-            animations.insert(
-                Action::Jump,
-                Rc::new(Animation {
-                    frames: vec![
-                        Rect {
-                            pos: Vec2i { x: 0, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 20, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32 as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 40, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 60, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 80, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 100, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                    ],
-                    frame_timings: vec![0, 10, 20, 30, 40, 50],
-                    loops: true,
-                }),
-            );
-            animations.insert(
-                Action::Walk,
-                Rc::new(Animation {
-                    frames: vec![
-                        Rect {
-                            pos: Vec2i { x: 0, y: 64 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 20, y: 64 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 40, y: 64 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 60, y: 64 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 80, y: 64 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                    ],
-                    frame_timings: vec![0, 10, 20, 30, 40],
-                    loops: true,
-                }),
-            );
-            animations.insert(
-                Action::Die,
-                Rc::new(Animation {
-                    frames: vec![Rect {
-                        pos: Vec2i { x: 40, y: 0 },
-                        sz: Vec2i {
-                            x: MARIO_RECT_WIDTH as i32,
-                            y: MARIO_RECT_HEIGHT as i32,
-                        },
-                    }],
-                    frame_timings: vec![0],
-                    loops: true,
-                }),
-            );
-            animations.insert(
-                Action::Stand,
-                Rc::new(Animation {
-                    frames: vec![Rect {
-                        pos: Vec2i { x: 20, y: 0 },
-                        sz: Vec2i {
-                            x: MARIO_RECT_WIDTH as i32,
-                            y: MARIO_RECT_HEIGHT as i32,
-                        },
-                    }],
-                    frame_timings: vec![0],
-                    loops: true,
-                }),
-            );
-        } else if character == Character::Luigi {
-            animations.insert(
-                Action::Jump,
-                Rc::new(Animation {
-                    frames: vec![
-                        Rect {
-                            pos: Vec2i { x: 120, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 140, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32 as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 160, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 180, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 200, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 220, y: 96 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                    ],
-                    frame_timings: vec![0, 10, 20, 30, 40, 50],
-                    loops: true,
-                }),
-            );
-            animations.insert(
-                Action::Walk,
-                Rc::new(Animation {
-                    frames: vec![
-                        Rect {
-                            pos: Vec2i { x: 120, y: 64 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 140, y: 64 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 160, y: 64 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 180, y: 64 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 200, y: 64 },
-                            sz: Vec2i {
-                                x: MARIO_RECT_WIDTH as i32,
-                                y: MARIO_RECT_HEIGHT as i32,
-                            },
-                        },
-                    ],
-                    frame_timings: vec![0, 10, 20, 30, 40],
-                    loops: true,
-                }),
-            );
-            animations.insert(
-                Action::Die,
-                Rc::new(Animation {
-                    frames: vec![Rect {
-                        pos: Vec2i { x: 160, y: 0 },
-                        sz: Vec2i {
-                            x: MARIO_RECT_WIDTH as i32,
-                            y: MARIO_RECT_HEIGHT as i32,
-                        },
-                    }],
-                    frame_timings: vec![0],
-                    loops: true,
-                }),
-            );
-            animations.insert(
-                Action::Stand,
-                Rc::new(Animation {
-                    frames: vec![Rect {
-                        pos: Vec2i { x: 140, y: 0 },
-                        sz: Vec2i {
-                            x: MARIO_RECT_WIDTH as i32,
-                            y: MARIO_RECT_HEIGHT as i32,
-                        },
-                    }],
-                    frame_timings: vec![0],
-                    loops: true,
-                }),
-            );
-        } else if character == Character::SpaceInvader {
-            animations.insert(
-                Action::Die,
-                Rc::new(Animation {
-                    frames: vec![
-                        Rect {
-                            pos: Vec2i { x: 37, y: 32 },
-                            sz: Vec2i {
-                                x: INVADER_PLAYER_RECT_WIDTH as i32,
-                                y: INVADER_PLAYER_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 37, y: 50 },
-                            sz: Vec2i {
-                                x: INVADER_PLAYER_RECT_WIDTH as i32,
-                                y: INVADER_PLAYER_RECT_HEIGHT as i32,
-                            },
-                        },
-                    ],
-                    frame_timings: vec![0, 10],
-                    loops: true,
-                }),
-            );
-            animations.insert(
-                Action::Glide,
-                Rc::new(Animation {
-                    frames: vec![Rect {
-                        pos: Vec2i { x: 37, y: 16 },
-                        sz: Vec2i {
-                            x: INVADER_PLAYER_RECT_WIDTH as i32,
-                            y: INVADER_PLAYER_RECT_HEIGHT as i32,
-                        },
-                    }],
-                    frame_timings: vec![0],
-                    loops: true,
-                }),
-            );
-        } else if character == Character::SpaceInvaderEnemy1 {
-            animations.insert(
-                Action::Glide,
-                Rc::new(Animation {
-                    frames: vec![
-                        Rect {
-                            pos: Vec2i { x: 0, y: 0 },
-                            sz: Vec2i {
-                                x: INVADER_ENEMY_RECT_WIDTH as i32,
-                                y: INVADER_ENEMY_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 16, y: 0 },
-                            sz: Vec2i {
-                                x: INVADER_ENEMY_RECT_WIDTH as i32,
-                                y: INVADER_ENEMY_RECT_HEIGHT as i32,
-                            },
-                        },
-                    ],
-                    frame_timings: vec![0, 10],
-                    loops: true,
-                }),
-            );
-            animations.insert(
-                Action::Die,
-                Rc::new(Animation {
-                    frames: vec![Rect {
-                        pos: Vec2i { x: 32, y: 0 },
-                        sz: Vec2i {
-                            x: INVADER_ENEMY_RECT_WIDTH as i32,
-                            y: INVADER_ENEMY_RECT_HEIGHT as i32,
-                        },
-                    }],
-                    frame_timings: vec![0],
-                    loops: true,
-                }),
-            );
-        } else if character == Character::SpaceInvaderEnemy2 {
-            animations.insert(
-                Action::Glide,
-                Rc::new(Animation {
-                    frames: vec![
-                        Rect {
-                            pos: Vec2i { x: 0, y: 16 },
-                            sz: Vec2i {
-                                x: INVADER_ENEMY_RECT_WIDTH as i32,
-                                y: INVADER_ENEMY_RECT_HEIGHT as i32,
-                            },
-                        },
-                        Rect {
-                            pos: Vec2i { x: 16, y: 16 },
-                            sz: Vec2i {
-                                x: INVADER_ENEMY_RECT_WIDTH as i32,
-                                y: INVADER_ENEMY_RECT_HEIGHT as i32,
-                            },
-                        },
-                    ],
-                    frame_timings: vec![0, 10],
-                    loops: true,
-                }),
-            );
-            animations.insert(
-                Action::Die,
-                Rc::new(Animation {
-                    frames: vec![Rect {
-                        pos: Vec2i { x: 32, y: 0 },
-                        sz: Vec2i {
-                            x: INVADER_ENEMY_RECT_WIDTH as i32,
-                            y: INVADER_ENEMY_RECT_HEIGHT as i32,
-                        },
-                    }],
-                    frame_timings: vec![0],
-                    loops: true,
-                }),
-            );
-        }
+    /// An empty set; fill it in with `add_animation` or use `from_atlas`
+    pub fn new(character: Character, image: Rc<Image>) -> Self {
         AnimationSet {
             character,
             image,
-            animations,
+            animations: HashMap::new(),
+            transitions: vec![],
+        }
+    }
+
+    /// Build a set from a plain text atlas, one animation strip per line:
+    ///
+    /// `action x y frame_w frame_h frame_count [loop|once]`
+    ///
+    /// Blank lines and lines starting with `#` are skipped.
+    pub fn from_atlas(character: Character, image: Rc<Image>, atlas: &str) -> Self {
+        let mut set = Self::new(character, image);
+        for (lineno, line) in atlas.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            assert!(
+                fields.len() == 6 || fields.len() == 7,
+                "atlas line {}: expected 6 or 7 fields, got {:?}",
+                lineno + 1,
+                line
+            );
+            let num = |i: usize| -> i32 {
+                fields[i].parse().unwrap_or_else(|_| {
+                    panic!("atlas line {}: {:?} is not a number", lineno + 1, fields[i])
+                })
+            };
+            let loops = fields.get(6) != Some(&"once");
+            set.add_animation(
+                Action::new(fields[0]),
+                Animation::strip(
+                    Vec2i {
+                        x: num(1),
+                        y: num(2),
+                    },
+                    Vec2i {
+                        x: num(3),
+                        y: num(4),
+                    },
+                    num(5) as usize,
+                    loops,
+                ),
+            );
         }
+        set
     }
 }
 
-// struct AnimQueue {
-//     queue: Vec<(f32, AnimationState, bool)>,
-// }
-
-// impl AnimQueue {
-//     #[allow(dead_code)]
-//     fn push(&mut self, p: f32, anim: AnimationState, pause: bool, retrigger: bool) {
-//         // If this is a retrigger, replace the old animation (if any)
-//         // otherwise, leave the old animation alone!
-//         let old_anim = anim.clone();
-//         let to_insert = if let Some(found_pos) = self
-//             .queue
-//             .iter()
-//             .position(|(qp, qanim, _)| qanim.animation == anim.animation)
-//         {
-//             let (_qp, qanim, _qpause) = self.queue.remove(found_pos);
-//             if retrigger {
-//                 (p, anim, pause)
-//             } else {
-//                 (p, qanim, pause)
-//             }
-//         } else {
-//             (p, anim, pause)
-//         };
-//         // put highest priority thing at end
-//         let pos = self
-//             .queue
-//             .iter()
-//             .rposition(|(qp, _, _)| qp < &p)
-//             .unwrap_or(0);
-//         self.queue.insert(pos, (p, old_anim, pause));
-//     }
-
-//     #[allow(dead_code)]
-//     fn tick(&mut self) {
-//         let qlen = self.queue.len();
-//         // tick possibly-paused non-current animations
-//         if qlen > 1 {
-//             for (_p, anim, pause) in self.queue.iter_mut().take(qlen - 2) {
-//                 if !(*pause) {
-//                     anim.tick();
-//                 }
-//             }
-//         }
-//         if let Some((_, active, _)) = self.queue.last() {
-//             active.tick();
-//         }
-//         // Throw away finished animations
-//         self.queue.retain(|(_p, anim, _)| !anim.is_finished());
-//     }
-
-//     // Got to return option here --- nothing to return if no animations in the queue!
-//     #[allow(dead_code)]
-//     fn current_frame(&self) -> Option<Rect> {
-//         self.queue
-//             .last()
-//             .map(|(_, anim, _)| anim.animation.current_frame(0, 0, 0))
-//     }
-// }
+/// Stand, walk and jump for side-view characters
+pub fn platformer_transitions() -> Vec<Transition> {
+    use Condition::*;
+    const WALK_SPEED: f32 = 0.5;
+    vec![
+        Transition::new(None, Action::Jump, vec![Grounded(false)]),
+        Transition::new(
+            Some(Action::Jump),
+            Action::Walk,
+            vec![Grounded(true), SpeedAtLeast(WALK_SPEED)],
+        ),
+        Transition::new(
+            Some(Action::Jump),
+            Action::Stand,
+            vec![Grounded(true), SpeedBelow(WALK_SPEED)],
+        ),
+        Transition::new(
+            Some(Action::Stand),
+            Action::Walk,
+            vec![SpeedAtLeast(WALK_SPEED)],
+        ),
+        Transition::new(
+            Some(Action::Walk),
+            Action::Stand,
+            vec![SpeedBelow(WALK_SPEED)],
+        ),
+    ]
+}
+
+/// A stack of animations layered by priority.
+/// The highest priority animation is the one shown; one-shot animations
+/// (hit, die) drop out of the queue when they finish, uncovering whatever
+/// was playing underneath.
+#[derive(Default)]
+pub struct AnimQueue {
+    // (priority, animation, pause lower layers), highest priority at the end
+    queue: Vec<(f32, AnimationState, bool)>,
+}
+
+impl AnimQueue {
+    pub fn new() -> Self {
+        Self { queue: vec![] }
+    }
+
+    /// Add an animation at priority `p`.
+    /// If `pause` is set, lower layers stop advancing while this one is queued.
+    /// If the same action is already queued, `retrigger` restarts it;
+    /// otherwise it keeps its progress and just takes the new priority.
+    pub fn push(&mut self, p: f32, anim: AnimationState, pause: bool, retrigger: bool) {
+        let to_insert = if let Some(found_pos) = self
+            .queue
+            .iter()
+            .position(|(_qp, qanim, _)| qanim.action == anim.action)
+        {
+            let (_qp, qanim, _qpause) = self.queue.remove(found_pos);
+            if retrigger {
+                anim
+            } else {
+                qanim
+            }
+        } else {
+            anim
+        };
+        // put highest priority thing at end; ties go to the newest push
+        let pos = self
+            .queue
+            .iter()
+            .position(|(qp, _, _)| *qp > p)
+            .unwrap_or(self.queue.len());
+        self.queue.insert(pos, (p, to_insert, pause));
+    }
+
+    /// Remove every queued animation for `action`
+    pub fn remove(&mut self, action: Action) {
+        self.queue.retain(|(_p, anim, _)| anim.action != action);
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    pub fn tick(&mut self, speedup_factor: &usize) {
+        // Walk down from the top; once we pass a pausing layer, everything below it holds still
+        let mut paused = false;
+        let qlen = self.queue.len();
+        for (i, (_p, anim, pause)) in self.queue.iter_mut().enumerate().rev() {
+            if i + 1 == qlen || !paused {
+                anim.tick(speedup_factor);
+            }
+            paused |= *pause;
+        }
+        // Throw away finished animations
+        self.queue
+            .retain(|(_p, anim, _)| !anim.is_finished(speedup_factor));
+    }
+
+    // Got to return option here --- nothing to return if no animations in the queue!
+    pub fn current_frame(&self, speedup_factor: &usize) -> Option<Rect> {
+        self.queue
+            .last()
+            .map(|(_, anim, _)| anim.current_frame(speedup_factor))
+    }
+
+    pub fn current_action(&self) -> Option<Action> {
+        self.queue.last().map(|(_, anim, _)| anim.action)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
@@ -0,0 +1,142 @@
+use crate::types::{Rect, Vec2i};
+
+/// A view onto the world: where the screen's top left corner is in world space
+pub struct Camera {
+    /// World position of the view's top left corner, before shake
+    pub pos: Vec2i,
+    /// How much of the world is visible, usually the framebuffer size
+    pub size: Vec2i,
+    /// Area of the view (in screen coordinates) a followed target can move
+    /// around in without the camera moving
+    pub dead_zone: Rect,
+    /// The view never shows anything outside this world rect, except while shaking
+    pub bounds: Option<Rect>,
+    shake_magnitude: i32,
+    shake_duration: usize,
+    shake_left: usize,
+    shake_offset: Vec2i,
+    rng: u32,
+}
+
+impl Camera {
+    pub fn new(size: Vec2i) -> Self {
+        Self {
+            pos: Vec2i { x: 0, y: 0 },
+            size,
+            // By default keep the target in the middle third of the screen
+            dead_zone: Rect {
+                pos: Vec2i {
+                    x: size.x / 3,
+                    y: size.y / 3,
+                },
+                sz: Vec2i {
+                    x: size.x / 3,
+                    y: size.y / 3,
+                },
+            },
+            bounds: None,
+            shake_magnitude: 0,
+            shake_duration: 0,
+            shake_left: 0,
+            shake_offset: Vec2i { x: 0, y: 0 },
+            rng: 0x9E37_79B9,
+        }
+    }
+
+    /// Where to draw from this frame, shake included. The shake goes on after clamping,
+    /// so it can show up to its magnitude past `bounds`; otherwise a map no bigger than
+    /// the view could never shake at all.
+    pub fn view(&self) -> Vec2i {
+        self.clamped(self.pos) + self.shake_offset
+    }
+
+    /// The world rect currently on screen
+    pub fn view_rect(&self) -> Rect {
+        Rect {
+            pos: self.view(),
+            sz: self.size,
+        }
+    }
+
+    pub fn to_screen(&self, world: Vec2i) -> Vec2i {
+        let view = self.view();
+        Vec2i {
+            x: world.x - view.x,
+            y: world.y - view.y,
+        }
+    }
+
+    pub fn to_world(&self, screen: Vec2i) -> Vec2i {
+        screen + self.view()
+    }
+
+    /// Move just enough to bring `target` back inside the dead zone
+    pub fn follow(&mut self, target: Rect) {
+        let dz = self.dead_zone;
+        let left = self.pos.x + dz.pos.x;
+        let right = left + dz.sz.x;
+        let top = self.pos.y + dz.pos.y;
+        let bottom = top + dz.sz.y;
+        if target.pos.x < left {
+            self.pos.x -= left - target.pos.x;
+        } else if target.pos.x + target.sz.x > right {
+            self.pos.x += target.pos.x + target.sz.x - right;
+        }
+        if target.pos.y < top {
+            self.pos.y -= top - target.pos.y;
+        } else if target.bottom() > bottom {
+            self.pos.y += target.bottom() - bottom;
+        }
+        self.clamp();
+    }
+
+    /// Keep the view inside `bounds`; if the bounds are smaller than the view, centre on them
+    pub fn clamp(&mut self) {
+        self.pos = self.clamped(self.pos);
+    }
+
+    fn clamped(&self, pos: Vec2i) -> Vec2i {
+        match self.bounds {
+            Some(b) => Vec2i {
+                x: clamp_axis(pos.x, self.size.x, b.pos.x, b.sz.x),
+                y: clamp_axis(pos.y, self.size.y, b.pos.y, b.sz.y),
+            },
+            None => pos,
+        }
+    }
+
+    /// Jolt the view by up to `magnitude` pixels, dying down over `duration` ticks
+    pub fn shake(&mut self, magnitude: i32, duration: usize) {
+        self.shake_magnitude = magnitude;
+        self.shake_duration = duration;
+        self.shake_left = duration;
+    }
+
+    pub fn tick(&mut self) {
+        if self.shake_left == 0 {
+            self.shake_offset = Vec2i { x: 0, y: 0 };
+            return;
+        }
+        let m = (self.shake_magnitude * self.shake_left as i32 / self.shake_duration as i32).abs();
+        let x = self.next_rand(m);
+        let y = self.next_rand(m);
+        self.shake_offset = Vec2i { x, y };
+        self.shake_left -= 1;
+    }
+
+    // xorshift; shake just needs to look random
+    fn next_rand(&mut self, m: i32) -> i32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng % (2 * m as u32 + 1)) as i32 - m
+    }
+}
+
+fn clamp_axis(pos: i32, view: i32, lo: i32, len: i32) -> i32 {
+    if len <= view {
+        lo - (view - len) / 2
+    } else {
+        pos.clamp(lo, lo + len - view)
+    }
+}
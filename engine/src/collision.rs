@@ -0,0 +1,384 @@
+//! Moving boxes through a Tilemap without passing through its solid tiles.
+//!
+//! Boxes move one axis at a time, horizontal first. Each move sweeps every cell between
+//! where the box starts and where it wants to go, so nothing tunnels through thin walls
+//! however fast it's going.
+
+use crate::tiles::{Tile, TileShape, Tilemap};
+use crate::types::{Rect, Vec2i};
+
+/// What a box touched after moving
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Contacts {
+    /// Standing on something, even if it didn't move down this frame
+    pub ground: bool,
+    pub ceiling: bool,
+    /// Ran into a wall on the left
+    pub left: bool,
+    /// Ran into a wall on the right
+    pub right: bool,
+    /// Ended up inside a solid tile, e.g. one was placed on top of it, so it's trapped
+    pub embedded: bool,
+    /// The cell of whatever the box is standing on, to look up friction and the like
+    pub ground_cell: Option<(i32, i32)>,
+    /// The index of the Solid the box is standing on, if it's standing on one
+    pub ground_solid: Option<usize>,
+}
+
+/// Changes to how `move_box_with` treats tiles
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct MoveOpts {
+    /// Fall through one-way platforms instead of landing on them
+    pub drop_through: bool,
+}
+
+impl Contacts {
+    pub fn any(&self) -> bool {
+        self.ground || self.ceiling || self.left || self.right
+    }
+}
+
+/// Something solid that isn't a tile, like a moving platform
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Solid {
+    pub rect: Rect,
+    /// Only solid from above, like a one-way tile
+    pub one_way: bool,
+}
+
+/// The part of a tile that blocks from every side, in world space
+pub(crate) fn solid_rect(tile: &Tile, corner: Vec2i, tsz: Vec2i) -> Option<Rect> {
+    match tile.shape {
+        TileShape::Full => Some(Rect {
+            pos: corner,
+            sz: tsz,
+        }),
+        TileShape::Half => Some(Rect {
+            pos: Vec2i {
+                x: corner.x,
+                y: corner.y + tsz.y / 2,
+            },
+            sz: Vec2i {
+                x: tsz.x,
+                y: tsz.y - tsz.y / 2,
+            },
+        }),
+        _ => None,
+    }
+}
+
+/// The y of a slope tile's surface at world column `x`
+fn slope_surface(left: f32, right: f32, corner: Vec2i, tsz: Vec2i, x: i32) -> i32 {
+    let t = ((x - corner.x) as f32 + 0.5) / tsz.x as f32;
+    let height = left + (right - left) * t.clamp(0.0, 1.0);
+    corner.y + tsz.y - (height * tsz.y as f32).round() as i32
+}
+
+/// The rows or columns of cells a span of pixels `a..=b` covers, in the order they're met
+fn cells_between(map: &Tilemap, a: i32, b: i32, horizontal: bool) -> Vec<i32> {
+    let cell = |p: i32| {
+        if horizontal {
+            map.cell_at(Vec2i { x: p, y: 0 }).0
+        } else {
+            map.cell_at(Vec2i { x: 0, y: p }).1
+        }
+    };
+    let (ca, cb) = (cell(a), cell(b));
+    if ca <= cb {
+        (ca..=cb).collect()
+    } else {
+        (cb..=ca).rev().collect()
+    }
+}
+
+fn feet_row(map: &Tilemap, rect: &Rect) -> i32 {
+    map.cell_at(Vec2i {
+        x: 0,
+        y: rect.bottom() - 1,
+    })
+    .1
+}
+
+/// The top of the highest solid tile a box has walked into the bottom half-tile of
+fn step_under(map: &Tilemap, rect: &Rect) -> Option<i32> {
+    let tsz = map.tileset().tile_sz();
+    let row = feet_row(map, rect);
+    cells_between(map, rect.pos.x, rect.pos.x + rect.sz.x - 1, true)
+        .into_iter()
+        .filter_map(|col| {
+            let corner = map.cell_corner((col, row));
+            let solid = solid_rect(map.tile_in_cell((col, row)), corner, tsz)?;
+            (solid.overlaps(*rect) && rect.bottom() - solid.pos.y <= tsz.y / 2)
+                .then_some(solid.pos.y)
+        })
+        .min()
+}
+
+/// The slope tile under the middle of a box's feet, with its surface height there
+fn slope_under(map: &Tilemap, rect: &Rect) -> Option<i32> {
+    let x = rect.pos.x + rect.sz.x / 2;
+    let foot = Vec2i {
+        x,
+        y: rect.bottom() - 1,
+    };
+    let (corner, tile) = map.tile_at(foot);
+    match tile.shape {
+        TileShape::Slope { left, right } => Some(slope_surface(
+            left,
+            right,
+            corner,
+            map.tileset().tile_sz(),
+            x,
+        )),
+        _ => None,
+    }
+}
+
+/// How far a box can go sideways, up to `dx`, and whether something stopped it
+fn sweep_x(map: &Tilemap, rect: &Rect, dx: i32) -> (i32, bool) {
+    if dx == 0 {
+        return (0, false);
+    }
+    let tsz = map.tileset().tile_sz();
+    let rows = cells_between(map, rect.pos.y, rect.bottom() - 1, false);
+    // Walking along a slope, the feet are inside the slope's row, and the tile at the top
+    // of the slope is a step to climb rather than a wall
+    let feet_row = slope_under(map, rect).map(|_| feet_row(map, rect));
+    let (lead, target) = if dx > 0 {
+        let right = rect.pos.x + rect.sz.x;
+        (right, right + dx - 1)
+    } else {
+        (rect.pos.x - 1, rect.pos.x + dx)
+    };
+    for col in cells_between(map, lead, target, true) {
+        let nearest = rows
+            .iter()
+            .filter_map(|&row| {
+                let corner = map.cell_corner((col, row));
+                let solid = solid_rect(map.tile_in_cell((col, row)), corner, tsz)?;
+                if solid.pos.y >= rect.bottom() || rect.pos.y >= solid.bottom() {
+                    return None;
+                }
+                if Some(row) == feet_row && rect.bottom() - solid.pos.y <= tsz.y / 2 {
+                    return None;
+                }
+                let d = if dx > 0 {
+                    solid.pos.x - (rect.pos.x + rect.sz.x)
+                } else {
+                    solid.pos.x + solid.sz.x - rect.pos.x
+                };
+                // Something we're already inside of doesn't get to pull us back
+                (d * dx.signum() >= 0).then_some(d)
+            })
+            .min_by_key(|d| d.abs());
+        if let Some(d) = nearest {
+            if d.abs() <= dx.abs() {
+                return (d, true);
+            }
+        }
+    }
+    (dx, false)
+}
+
+/// How far a box can go vertically, up to `dy`, and the cell it hit if something stopped it
+fn sweep_y(map: &Tilemap, rect: &Rect, dy: i32, opts: MoveOpts) -> (i32, Option<(i32, i32)>) {
+    if dy == 0 {
+        return (0, None);
+    }
+    let tsz = map.tileset().tile_sz();
+    let cols = cells_between(map, rect.pos.x, rect.pos.x + rect.sz.x - 1, true);
+    let mid = rect.pos.x + rect.sz.x / 2;
+    let (lead, target) = if dy > 0 {
+        (rect.bottom(), rect.bottom() + dy - 1)
+    } else {
+        (rect.pos.y - 1, rect.pos.y + dy)
+    };
+    for row in cells_between(map, lead, target, false) {
+        let nearest = cols
+            .iter()
+            .filter_map(|&col| {
+                let corner = map.cell_corner((col, row));
+                let tile = map.tile_in_cell((col, row));
+                let d = if dy > 0 {
+                    // Falling: anything with a top can catch us
+                    let top = match tile.shape {
+                        TileShape::Full | TileShape::Half => solid_rect(tile, corner, tsz)?.pos.y,
+                        TileShape::OneWay if !opts.drop_through => corner.y,
+                        // Slopes only hold up the middle of the box
+                        TileShape::Slope { left, right }
+                            if corner.x <= mid && mid < corner.x + tsz.x =>
+                        {
+                            slope_surface(left, right, corner, tsz, mid)
+                        }
+                        _ => return None,
+                    };
+                    top - rect.bottom()
+                } else {
+                    // Rising: only things solid from below stop us
+                    solid_rect(tile, corner, tsz)?.bottom() - rect.pos.y
+                };
+                (d * dy.signum() >= 0).then_some((d, (col, row)))
+            })
+            .min_by_key(|(d, _)| d.abs());
+        if let Some((d, cell)) = nearest {
+            if d.abs() <= dy.abs() {
+                return (d, Some(cell));
+            }
+        }
+    }
+    (dy, None)
+}
+
+/// How far a box can go sideways through `solids`, if one of them stops it within `dx`
+fn sweep_solids_x(solids: &[Solid], rect: &Rect, dx: i32) -> Option<i32> {
+    if dx == 0 {
+        return None;
+    }
+    solids
+        .iter()
+        .filter(|s| !s.one_way && s.rect.pos.y < rect.bottom() && rect.pos.y < s.rect.bottom())
+        .filter_map(|s| {
+            let d = if dx > 0 {
+                s.rect.pos.x - (rect.pos.x + rect.sz.x)
+            } else {
+                s.rect.pos.x + s.rect.sz.x - rect.pos.x
+            };
+            (d * dx.signum() >= 0 && d.abs() <= dx.abs()).then_some(d)
+        })
+        .min_by_key(|d| d.abs())
+}
+
+/// How far a box can go vertically through `solids`, and which one stopped it within `dy`
+fn sweep_solids_y(solids: &[Solid], rect: &Rect, dy: i32, opts: MoveOpts) -> Option<(i32, usize)> {
+    if dy == 0 {
+        return None;
+    }
+    solids
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| {
+            s.rect.pos.x < rect.pos.x + rect.sz.x && rect.pos.x < s.rect.pos.x + s.rect.sz.x
+        })
+        .filter_map(|(i, s)| {
+            let d = if dy > 0 {
+                if s.one_way && opts.drop_through {
+                    return None;
+                }
+                s.rect.pos.y - rect.bottom()
+            } else if s.one_way {
+                return None;
+            } else {
+                s.rect.bottom() - rect.pos.y
+            };
+            (d * dy.signum() >= 0 && d.abs() <= dy.abs()).then_some((d, i))
+        })
+        .min_by_key(|(d, _)| d.abs())
+}
+
+/// Whether a box overlaps any tile that's solid from every side
+pub fn overlaps_solid(map: &Tilemap, rect: &Rect) -> bool {
+    let tsz = map.tileset().tile_sz();
+    for row in cells_between(map, rect.pos.y, rect.bottom() - 1, false) {
+        for col in cells_between(map, rect.pos.x, rect.pos.x + rect.sz.x - 1, true) {
+            let corner = map.cell_corner((col, row));
+            if let Some(solid) = solid_rect(map.tile_in_cell((col, row)), corner, tsz) {
+                if solid.overlaps(*rect) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+fn ground_cell(map: &Tilemap, rect: &Rect, opts: MoveOpts) -> Option<(i32, i32)> {
+    match sweep_y(map, rect, 1, opts) {
+        (0, Some(cell)) => Some(cell),
+        _ => None,
+    }
+}
+
+/// What's directly under a box, if it's standing on anything
+pub fn ground_under(map: &Tilemap, rect: &Rect) -> Option<(i32, i32)> {
+    ground_cell(map, rect, MoveOpts::default())
+}
+
+/// Drop a box up to `max` pixels onto the ground below it, if there's ground that close.
+/// Keeps things walking down slopes and steps glued to the floor.
+pub fn snap_down(map: &Tilemap, rect: &mut Rect, max: i32) -> bool {
+    match sweep_y(map, rect, max, MoveOpts::default()) {
+        (d, Some(_)) => {
+            rect.pos.y += d;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Move a box by `delta` through `map`, stopping against solid tiles,
+/// and report which sides of it touched something
+pub fn move_box(map: &Tilemap, rect: &mut Rect, delta: Vec2i) -> Contacts {
+    move_box_with(map, rect, delta, MoveOpts::default())
+}
+
+/// Like move_box, with some tiles treated differently
+pub fn move_box_with(map: &Tilemap, rect: &mut Rect, delta: Vec2i, opts: MoveOpts) -> Contacts {
+    move_box_among(map, rect, delta, opts, &[])
+}
+
+/// Like move_box_with, also stopping against `solids`
+pub fn move_box_among(
+    map: &Tilemap,
+    rect: &mut Rect,
+    delta: Vec2i,
+    opts: MoveOpts,
+    solids: &[Solid],
+) -> Contacts {
+    let mut contacts = Contacts::default();
+
+    let on_slope = slope_under(map, rect).is_some();
+    let (mut dx, mut hit) = sweep_x(map, rect, delta.x);
+    if let Some(d) = sweep_solids_x(solids, rect, delta.x) {
+        if d.abs() <= dx.abs() {
+            dx = d;
+            hit = true;
+        }
+    }
+    rect.pos.x += dx;
+    if hit {
+        contacts.left = delta.x < 0;
+        contacts.right = delta.x > 0;
+    }
+    // Walking up a slope lifts the box onto it, and onto whatever's at the top of it
+    if delta.y >= 0 {
+        let step = on_slope.then(|| step_under(map, rect)).flatten();
+        let surface = slope_under(map, rect).into_iter().chain(step).min();
+        if let Some(surface) = surface {
+            if rect.bottom() > surface {
+                rect.pos.y = surface - rect.sz.y;
+            }
+        }
+    }
+
+    let (mut dy, hit) = sweep_y(map, rect, delta.y, opts);
+    let mut hit = hit.is_some();
+    if let Some((d, _)) = sweep_solids_y(solids, rect, delta.y, opts) {
+        if d.abs() <= dy.abs() {
+            dy = d;
+            hit = true;
+        }
+    }
+    rect.pos.y += dy;
+    if hit && delta.y < 0 {
+        contacts.ceiling = true;
+    }
+
+    contacts.ground_cell = ground_cell(map, rect, opts);
+    contacts.ground_solid = match sweep_solids_y(solids, rect, 1, opts) {
+        Some((0, i)) => Some(i),
+        _ => None,
+    };
+    contacts.ground = contacts.ground_cell.is_some() || contacts.ground_solid.is_some();
+    contacts.embedded = overlaps_solid(map, rect);
+    contacts
+}
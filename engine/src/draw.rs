@@ -0,0 +1,247 @@
+use crate::camera::Camera;
+use crate::sprite::Sprite;
+use crate::types::{Color, Image, Rect, Vec2i};
+
+use std::rc::Rc;
+
+/// Handy layers; anything in between works too
+pub const BACKGROUND: i32 = -100;
+pub const WORLD: i32 = 0;
+pub const FOREGROUND: i32 = 50;
+pub const HUD: i32 = 100;
+
+/// A fixed-size bitmap font: `chars[i]` is the i-th glyph in the sheet,
+/// read left to right from `origin` and wrapping at the sheet's edge
+pub struct Font {
+    pub image: Rc<Image>,
+    pub glyph_sz: Vec2i,
+    pub origin: Vec2i,
+    pub chars: String,
+}
+
+impl Font {
+    pub fn glyph_rect(&self, c: char) -> Option<Rect> {
+        let idx = self.chars.chars().position(|fc| fc == c)? as i32;
+        let per_row = (self.image.sz.x - self.origin.x) / self.glyph_sz.x;
+        Some(Rect {
+            pos: Vec2i {
+                x: self.origin.x + (idx % per_row) * self.glyph_sz.x,
+                y: self.origin.y + (idx / per_row) * self.glyph_sz.y,
+            },
+            sz: self.glyph_sz,
+        })
+    }
+
+    pub fn text_size(&self, text: &str) -> Vec2i {
+        Vec2i {
+            x: text.chars().count() as i32 * self.glyph_sz.x,
+            y: self.glyph_sz.y,
+        }
+    }
+}
+
+pub enum DrawKind {
+    Blit {
+        image: Rc<Image>,
+        from: Rect,
+        flip: bool,
+        alpha: u8,
+    },
+    /// An image with no see-through pixels, copied straight over what's there
+    Copy {
+        image: Rc<Image>,
+        from: Rect,
+    },
+    Rect {
+        color: Color,
+    },
+    Ball {
+        color: Color,
+    },
+    Text {
+        font: Rc<Font>,
+        text: String,
+    },
+}
+
+pub struct DrawCmd {
+    pub layer: i32,
+    /// Sort key within a layer; sprites use their bottom edge so lower ones overlap higher ones
+    pub z: i32,
+    /// Screen area covered, used for culling
+    pub bounds: Rect,
+    pub kind: DrawKind,
+}
+
+/// Collects a frame's draw commands so they can be drawn back to front,
+/// no matter what order game code issued them in.
+///
+/// The helper methods take world coordinates and shift them by the camera view;
+/// anything on the HUD layer or above stays in screen coordinates.
+/// `push` takes commands already in screen coordinates.
+#[derive(Default)]
+pub struct DrawList {
+    cmds: Vec<DrawCmd>,
+    view: Vec2i,
+}
+
+impl DrawList {
+    pub fn new() -> Self {
+        Self {
+            cmds: vec![],
+            view: Vec2i { x: 0, y: 0 },
+        }
+    }
+
+    /// Draw the world from this camera's point of view
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.view = camera.view();
+    }
+
+    pub fn push(&mut self, cmd: DrawCmd) {
+        self.cmds.push(cmd);
+    }
+
+    fn to_screen(&self, layer: i32, pos: Vec2i) -> Vec2i {
+        if layer >= HUD {
+            pos
+        } else {
+            Vec2i {
+                x: pos.x - self.view.x,
+                y: pos.y - self.view.y,
+            }
+        }
+    }
+
+    pub fn blit(&mut self, layer: i32, image: &Rc<Image>, from: Rect, to: Vec2i, flip: bool) {
+        self.blit_alpha(layer, image, from, to, flip, 255);
+    }
+
+    pub fn blit_alpha(
+        &mut self,
+        layer: i32,
+        image: &Rc<Image>,
+        from: Rect,
+        to: Vec2i,
+        flip: bool,
+        alpha: u8,
+    ) {
+        self.push(DrawCmd {
+            layer,
+            z: 0,
+            bounds: Rect {
+                pos: self.to_screen(layer, to),
+                sz: from.sz,
+            },
+            kind: DrawKind::Blit {
+                image: image.clone(),
+                from,
+                flip,
+                alpha,
+            },
+        });
+    }
+
+    /// Queue a sprite on its own layer, y-sorted by its bottom edge
+    pub fn sprite(&mut self, s: &Sprite) {
+        if !s.visible {
+            return;
+        }
+        self.push(DrawCmd {
+            layer: s.layer,
+            z: s.shape.bottom(),
+            bounds: Rect {
+                pos: self.to_screen(s.layer, s.draw_pos()),
+                sz: s.frame.sz,
+            },
+            kind: DrawKind::Blit {
+                image: s.image.clone(),
+                from: s.frame,
+                flip: s.flip,
+                alpha: 255,
+            },
+        });
+    }
+
+    pub fn rect(&mut self, layer: i32, rect: Rect, color: Color) {
+        self.push(DrawCmd {
+            layer,
+            z: 0,
+            bounds: Rect {
+                pos: self.to_screen(layer, rect.pos),
+                sz: rect.sz,
+            },
+            kind: DrawKind::Rect { color },
+        });
+    }
+
+    pub fn ball(&mut self, layer: i32, rect: Rect, color: Color) {
+        self.push(DrawCmd {
+            layer,
+            z: 0,
+            bounds: Rect {
+                pos: self.to_screen(layer, rect.pos),
+                sz: rect.sz,
+            },
+            kind: DrawKind::Ball { color },
+        });
+    }
+
+    pub fn text(&mut self, layer: i32, font: &Rc<Font>, text: &str, pos: Vec2i) {
+        self.push(DrawCmd {
+            layer,
+            z: 0,
+            bounds: Rect {
+                pos: self.to_screen(layer, pos),
+                sz: font.text_size(text),
+            },
+            kind: DrawKind::Text {
+                font: font.clone(),
+                text: text.to_string(),
+            },
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.cmds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cmds.is_empty()
+    }
+
+    /// Sort, cull and draw everything queued into `fb`, leaving the list empty for the next frame
+    pub fn flush(&mut self, fb: &mut Image) {
+        let screen = Rect {
+            pos: Vec2i { x: 0, y: 0 },
+            sz: fb.sz,
+        };
+        // Stable, so commands with equal keys keep the order they were queued in
+        self.cmds.sort_by_key(|cmd| (cmd.layer, cmd.z));
+        for cmd in self.cmds.drain(..) {
+            if !screen.overlaps(cmd.bounds) {
+                continue;
+            }
+            match cmd.kind {
+                DrawKind::Blit {
+                    image,
+                    from,
+                    flip,
+                    alpha,
+                } => fb.bitblt_alpha(&image, from, cmd.bounds.pos, flip, alpha),
+                DrawKind::Copy { image, from } => fb.copy_rect(&image, from, cmd.bounds.pos),
+                DrawKind::Rect { color } => fb.draw_rect(&cmd.bounds, color),
+                DrawKind::Ball { color } => fb.draw_ball(&cmd.bounds, color),
+                DrawKind::Text { font, text } => {
+                    let mut pos = cmd.bounds.pos;
+                    for c in text.chars() {
+                        if let Some(glyph) = font.glyph_rect(c) {
+                            fb.bitblt(&font.image, glyph, pos, false);
+                        }
+                        pos.x += font.glyph_sz.x;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Game objects as entities with components, plus the systems the games share.
+//!
+//! An Entity is just an id; its data lives in one Storage per component type, indexed by
+//! the entity, and `Storage::join` finds the entities that have two components at once.
+//! Ids carry a generation, so an id kept around after its entity is gone never finds
+//! whatever reused the slot. Despawning is deferred to `World::maintain`, so
+//! a system can despawn things while other systems are still looking at them.
+
+use crate::draw::DrawList;
+use crate::overlap::Overlaps;
+use crate::sprite::Sprite;
+use crate::types::{Color, Rect, Vec2i};
+
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    /// Which slot it's in; only unique among entities alive at the same time
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+}
+
+/// Every component of one type, by entity
+pub struct Storage<T> {
+    /// Each value is tagged with the generation of the entity that owns it
+    slots: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Storage<T> {
+    fn new() -> Self {
+        Self { slots: vec![] }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        match self.slots.get(entity.index())? {
+            Some((generation, value)) if *generation == entity.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(entity.index())? {
+            Some((generation, value)) if *generation == entity.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    /// Give an entity this component, returning the one it replaced
+    pub fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        if self.slots.len() <= entity.index() {
+            self.slots.resize_with(entity.index() + 1, || None);
+        }
+        let old = self.remove(entity);
+        self.slots[entity.index()] = Some((entity.generation, value));
+        old
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let slot = self.slots.get_mut(entity.index())?;
+        match slot {
+            Some((generation, _)) if *generation == entity.generation => {
+                slot.take().map(|(_, value)| value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Every entity with this component, in index order
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            let (generation, value) = slot.as_ref()?;
+            let entity = Entity {
+                index: i as u32,
+                generation: *generation,
+            };
+            Some((entity, value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(i, slot)| {
+            let (generation, value) = slot.as_mut()?;
+            let entity = Entity {
+                index: i as u32,
+                generation: *generation,
+            };
+            Some((entity, value))
+        })
+    }
+
+    /// Every entity with both this component and one in `other`, in index order
+    pub fn join<'a, U>(
+        &'a self,
+        other: &'a Storage<U>,
+    ) -> impl Iterator<Item = (Entity, &'a T, &'a U)> {
+        self.iter()
+            .filter_map(move |(entity, value)| Some((entity, value, other.get(entity)?)))
+    }
+
+    /// Like `join`, but this storage's components can be changed
+    pub fn join_mut<'a, U>(
+        &'a mut self,
+        other: &'a Storage<U>,
+    ) -> impl Iterator<Item = (Entity, &'a mut T, &'a U)> {
+        self.iter_mut()
+            .filter_map(move |(entity, value)| Some((entity, value, other.get(entity)?)))
+    }
+}
+
+/// What the World needs from a Storage without knowing its component type
+trait AnyStorage {
+    fn remove_entity(&mut self, entity: Entity);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove(entity);
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub struct World {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    /// Slots whose entities are gone, ready to reuse
+    free: Vec<u32>,
+    /// Despawned entities waiting for the next maintain
+    doomed: Vec<Entity>,
+    /// Each storage borrows separately, so a system can read one while changing another
+    storages: HashMap<TypeId, RefCell<Box<dyn AnyStorage>>>,
+}
+
+impl World {
+    /// A world that already has storage for the engine's own components
+    pub fn new() -> Self {
+        let mut world = Self {
+            generations: vec![],
+            alive: vec![],
+            free: vec![],
+            doomed: vec![],
+            storages: HashMap::new(),
+        };
+        world.register::<Rect>();
+        world.register::<Velocity>();
+        world.register::<Fill>();
+        world.register::<Sprite>();
+        world
+    }
+
+    /// Make storage for a component type; inserting one does this too
+    pub fn register<T: 'static>(&mut self) {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| RefCell::new(Box::new(Storage::<T>::new())));
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.generations.push(0);
+                self.alive.push(false);
+                (self.generations.len() - 1) as u32
+            }
+        };
+        self.alive[index as usize] = true;
+        Entity {
+            index,
+            generation: self.generations[index as usize],
+        }
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.generations.get(entity.index()) == Some(&entity.generation)
+            && self.alive[entity.index()]
+    }
+
+    /// Mark an entity for removal at the next `maintain`. It stops being alive right away,
+    /// but its components stay put until then, so storages still iterate over it.
+    pub fn despawn(&mut self, entity: Entity) {
+        if self.is_alive(entity) {
+            self.alive[entity.index()] = false;
+            self.doomed.push(entity);
+        }
+    }
+
+    /// Remove everything despawned since the last call, returning what went
+    pub fn maintain(&mut self) -> Vec<Entity> {
+        let doomed = std::mem::take(&mut self.doomed);
+        for &entity in &doomed {
+            for storage in self.storages.values_mut() {
+                storage.get_mut().remove_entity(entity);
+            }
+            self.generations[entity.index()] += 1;
+            self.free.push(entity.index);
+        }
+        doomed
+    }
+
+    /// Every live entity, in index order
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive
+            .iter()
+            .enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(|(i, _)| Entity {
+                index: i as u32,
+                generation: self.generations[i],
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.alive.iter().filter(|alive| **alive).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn cell<T: 'static>(&self) -> &RefCell<Box<dyn AnyStorage>> {
+        self.storages.get(&TypeId::of::<T>()).unwrap_or_else(|| {
+            panic!(
+                "no storage for {}; register it first",
+                std::any::type_name::<T>()
+            )
+        })
+    }
+
+    /// All the components of one type; panics if the type was never registered
+    pub fn storage<T: 'static>(&self) -> Ref<'_, Storage<T>> {
+        Ref::map(self.cell::<T>().borrow(), |s| {
+            s.as_any().downcast_ref::<Storage<T>>().unwrap()
+        })
+    }
+
+    pub fn storage_mut<T: 'static>(&self) -> RefMut<'_, Storage<T>> {
+        RefMut::map(self.cell::<T>().borrow_mut(), |s| {
+            s.as_any_mut().downcast_mut::<Storage<T>>().unwrap()
+        })
+    }
+
+    /// Give a live entity a component, returning the one it replaced
+    pub fn insert<T: 'static>(&mut self, entity: Entity, value: T) -> Option<T> {
+        assert!(self.is_alive(entity), "{:?} isn't alive", entity);
+        self.register::<T>();
+        self.storage_mut::<T>().insert(entity, value)
+    }
+
+    fn registered<T: 'static>(&self) -> bool {
+        self.storages.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Take a component off an entity; None if it had none, or the type was never registered
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        if !self.registered::<T>() {
+            return None;
+        }
+        self.storage_mut::<T>().remove(entity)
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        if !self.registered::<T>() {
+            return None;
+        }
+        Ref::filter_map(self.storage::<T>(), |s| s.get(entity)).ok()
+    }
+
+    pub fn get_mut<T: 'static>(&self, entity: Entity) -> Option<RefMut<'_, T>> {
+        if !self.registered::<T>() {
+            return None;
+        }
+        RefMut::filter_map(self.storage_mut::<T>(), |s| s.get_mut(entity)).ok()
+    }
+
+    pub fn has<T: 'static>(&self, entity: Entity) -> bool {
+        self.registered::<T>() && self.storage::<T>().contains(entity)
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pixels per frame an entity's Rect moves
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Velocity(pub Vec2i);
+
+/// Draws an entity's Rect as a solid block
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Fill {
+    pub layer: i32,
+    pub color: Color,
+}
+
+/// Move every Rect that has a Velocity
+pub fn movement(world: &World) {
+    let velocities = world.storage::<Velocity>();
+    for (_, rect, velocity) in world.storage_mut::<Rect>().join_mut(&velocities) {
+        rect.move_by(velocity.0.x, velocity.0.y);
+    }
+}
+
+/// Put every Sprite on its entity's Rect, if it has one, and step its animation
+pub fn animation(world: &World, speedup_factor: usize) {
+    let rects = world.storage::<Rect>();
+    let mut sprites = world.storage_mut::<Sprite>();
+    for (_, sprite, rect) in sprites.join_mut(&rects) {
+        sprite.shape.pos = rect.pos;
+    }
+    for (_, sprite) in sprites.iter_mut() {
+        sprite.tick_animation(&speedup_factor);
+    }
+}
+
+/// Queue every Sprite, and every Rect with a Fill, for drawing
+pub fn render(world: &World, list: &mut DrawList) {
+    let rects = world.storage::<Rect>();
+    for (_, fill, rect) in world.storage::<Fill>().join(&rects) {
+        list.rect(fill.layer, *rect, fill.color);
+    }
+    for (_, sprite) in world.storage::<Sprite>().iter() {
+        list.sprite(sprite);
+    }
+}
+
+/// Move the bodies in `overlaps` to their entities' Rects; `key` gives an entity's body
+pub fn collision<K: Copy + Eq + Hash + Ord>(
+    world: &World,
+    overlaps: &mut Overlaps<K>,
+    key: impl Fn(Entity) -> K,
+) {
+    for (entity, rect) in world.storage::<Rect>().iter() {
+        let key = key(entity);
+        if overlaps.contains(key) {
+            overlaps.update(key, *rect);
+        }
+    }
+}
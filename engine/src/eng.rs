@@ -56,6 +56,239 @@ pub trait Game {
     fn render(state: &mut Self::State, assets: &mut Self::Assets, fb: &mut Image);
 }
 
+/// One screen of a game: a title, the game itself, a pause menu. Scenes live on a
+/// SceneStack, and only the one on top gets updated and rendered.
+pub trait Scene<A> {
+    fn update(&mut self, assets: &mut A, keys: &[bool], prev_keys: &[bool]) -> SceneChange<A>;
+    /// While the stack is fading this is only called at the start of each half of the
+    /// fade, and that frame is held, so a scene that moves things in here stays put
+    fn render(&mut self, assets: &mut A, fb: &mut Image);
+    /// Drawn over a still of whatever was on screen when it got to the top, like a pause
+    /// menu over the paused game
+    fn overlay(&self) -> bool {
+        false
+    }
+}
+
+/// What a scene's update wants done to the stack
+pub enum SceneChange<A> {
+    None,
+    Push(Box<dyn Scene<A>>),
+    /// Back to the scene underneath; the last scene on the stack can't be popped
+    Pop,
+    Replace(Box<dyn Scene<A>>),
+    /// Replace the top scene with one built only once the change is made, so a new game
+    /// behind a Fade doesn't start until the screen is black
+    ReplaceWith(fn(&mut A) -> Box<dyn Scene<A>>),
+    /// Fade to black over this many frames, make the change, then fade back in
+    Fade(usize, Box<SceneChange<A>>),
+}
+
+struct Fading<A> {
+    /// Still to do once the screen is black; None while fading back in
+    then: Option<SceneChange<A>>,
+    frames: usize,
+    t: usize,
+    /// The top scene's frame, held for this half of the fade
+    still: Option<Image>,
+}
+
+pub struct SceneStack<A> {
+    scenes: Vec<Box<dyn Scene<A>>>,
+    /// The still under each overlay scene, once it's been taken
+    backdrops: Vec<Option<Image>>,
+    fade: Option<Fading<A>>,
+}
+
+impl<A> SceneStack<A> {
+    pub fn new(first: Box<dyn Scene<A>>) -> Self {
+        Self {
+            scenes: vec![first],
+            backdrops: vec![None],
+            fade: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scenes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    fn apply(&mut self, assets: &mut A, change: SceneChange<A>) {
+        match change {
+            SceneChange::None => {}
+            SceneChange::Push(scene) => {
+                self.scenes.push(scene);
+                self.backdrops.push(None);
+            }
+            SceneChange::Pop => {
+                // With nothing left on the stack there'd be nothing to update or draw
+                debug_assert!(self.scenes.len() > 1, "can't pop the last scene");
+                if self.scenes.len() > 1 {
+                    self.scenes.pop();
+                    self.backdrops.pop();
+                }
+            }
+            SceneChange::Replace(scene) => {
+                self.scenes.pop();
+                self.backdrops.pop();
+                self.apply(assets, SceneChange::Push(scene));
+            }
+            SceneChange::ReplaceWith(new_scene) => {
+                let scene = new_scene(assets);
+                self.apply(assets, SceneChange::Replace(scene));
+            }
+            SceneChange::Fade(frames, then) => {
+                self.fade = Some(Fading {
+                    then: Some(*then),
+                    frames: frames.max(1),
+                    t: 0,
+                    still: None,
+                });
+            }
+        }
+    }
+
+    /// Update the top scene, or carry on fading; nothing takes input mid-fade
+    pub fn update(&mut self, assets: &mut A, keys: &[bool], prev_keys: &[bool]) {
+        if let Some(fade) = &mut self.fade {
+            fade.t += 1;
+            if fade.t >= fade.frames {
+                fade.t = 0;
+                fade.still = None;
+                match fade.then.take() {
+                    Some(then) => self.apply(assets, then),
+                    None => self.fade = None,
+                }
+            }
+            return;
+        }
+        if let Some(scene) = self.scenes.last_mut() {
+            let change = scene.update(assets, keys, prev_keys);
+            self.apply(assets, change);
+        }
+    }
+
+    pub fn render(&mut self, assets: &mut A, fb: &mut Image) {
+        let Some(scene) = self.scenes.last_mut() else {
+            return;
+        };
+        if let Some(Fading {
+            still: Some(still), ..
+        }) = &self.fade
+        {
+            fb.buffer.copy_from_slice(&still.buffer);
+        } else {
+            Self::render_top(
+                scene.as_mut(),
+                self.backdrops.last_mut().unwrap(),
+                assets,
+                fb,
+            );
+        }
+        if let Some(fade) = &mut self.fade {
+            if fade.still.is_none() {
+                fade.still = Some(fb.clone());
+            }
+            let t = fade.t as f32 / fade.frames as f32;
+            darken(fb, if fade.then.is_some() { t } else { 1.0 - t });
+        }
+    }
+
+    fn render_top(
+        scene: &mut dyn Scene<A>,
+        backdrop: &mut Option<Image>,
+        assets: &mut A,
+        fb: &mut Image,
+    ) {
+        if scene.overlay() {
+            // The framebuffer still has the last frame from before this scene was on top
+            match backdrop {
+                Some(still) => fb.buffer.copy_from_slice(&still.buffer),
+                None => *backdrop = Some(fb.clone()),
+            }
+        }
+        scene.render(assets, fb);
+    }
+}
+
+/// Blend the whole frame towards black; 0.0 leaves it alone and 1.0 is all black
+pub fn darken(fb: &mut Image, amount: f32) {
+    let keep = 1.0 - amount.clamp(0.0, 1.0);
+    for (r, g, b, _) in fb.buffer.iter_mut() {
+        *r = (*r as f32 * keep) as u8;
+        *g = (*g as f32 * keep) as u8;
+        *b = (*b as f32 * keep) as u8;
+    }
+}
+
+/// Whether a key went down this frame
+pub fn pressed(key: usize, keys: &[bool], prev_keys: &[bool]) -> bool {
+    keys[key] && !prev_keys[key]
+}
+
+/// Dims the paused scene until `key` is pressed again
+pub struct Paused {
+    key: usize,
+}
+
+impl Paused {
+    pub fn new(key: usize) -> Self {
+        Self { key }
+    }
+}
+
+impl<A> Scene<A> for Paused {
+    fn update(&mut self, _assets: &mut A, keys: &[bool], prev_keys: &[bool]) -> SceneChange<A> {
+        if pressed(self.key, keys, prev_keys) {
+            SceneChange::Pop
+        } else {
+            SceneChange::None
+        }
+    }
+    fn render(&mut self, _assets: &mut A, fb: &mut Image) {
+        darken(fb, 0.5);
+    }
+    fn overlay(&self) -> bool {
+        true
+    }
+}
+
+/// Holds the last frame of a finished game until `key` is pressed, then fades into a new one
+pub struct Restart<A> {
+    key: usize,
+    fade_frames: usize,
+    new_game: fn(&mut A) -> Box<dyn Scene<A>>,
+}
+
+impl<A> Restart<A> {
+    pub fn new(key: usize, fade_frames: usize, new_game: fn(&mut A) -> Box<dyn Scene<A>>) -> Self {
+        Self {
+            key,
+            fade_frames,
+            new_game,
+        }
+    }
+}
+
+impl<A> Scene<A> for Restart<A> {
+    fn update(&mut self, _assets: &mut A, keys: &[bool], prev_keys: &[bool]) -> SceneChange<A> {
+        if pressed(self.key, keys, prev_keys) {
+            let new_game = SceneChange::ReplaceWith(self.new_game);
+            SceneChange::Fade(self.fade_frames, Box::new(new_game))
+        } else {
+            SceneChange::None
+        }
+    }
+    fn render(&mut self, _assets: &mut A, _fb: &mut Image) {}
+    fn overlay(&self) -> bool {
+        true
+    }
+}
+
 pub fn go<GameT: Game + 'static>() {
     let (mut state, mut assets) = GameT::new();
     let event_loop = EventLoop::new();
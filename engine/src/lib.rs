@@ -1,5 +1,16 @@
 pub mod animations;
+pub mod camera;
+pub mod collision;
+pub mod draw;
+pub mod ecs;
 pub mod eng;
+pub mod mask;
+pub mod overlap;
+pub mod platformer;
+pub mod platforms;
+pub mod projectile;
+pub mod raycast;
+pub mod spatial;
 pub mod sprite;
 pub mod tiles;
 pub mod types;
\ No newline at end of file
@@ -0,0 +1,179 @@
+//! Collision shapes taken from the pixels a sprite actually draws.
+//!
+//! A Mask is one bit per pixel, set wherever the source image isn't transparent. Overlap
+//! tests check bounding boxes first and only compare bits where the boxes meet, 64 pixels
+//! at a time.
+
+use crate::animations::AnimationSet;
+use crate::sprite::Sprite;
+use crate::types::{Image, Rect, Vec2i};
+
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Mask {
+    sz: Vec2i,
+    /// Words per row
+    pitch: usize,
+    bits: Vec<u64>,
+}
+
+impl Mask {
+    pub fn empty(sz: Vec2i) -> Self {
+        let pitch = (sz.x.max(0) as usize).div_ceil(64);
+        Self {
+            sz,
+            pitch,
+            bits: vec![0; pitch * sz.y.max(0) as usize],
+        }
+    }
+
+    /// Every pixel of `from` that isn't fully transparent, mirrored if `flip` like bitblt
+    pub fn from_alpha(image: &Image, from: Rect, flip: bool) -> Self {
+        let mut mask = Self::empty(from.sz);
+        for y in 0..from.sz.y {
+            let row = ((from.pos.y + y) * image.sz.x + from.pos.x) as usize;
+            for x in 0..from.sz.x {
+                if image.buffer[row + x as usize].3 > 0 {
+                    let mx = if flip { from.sz.x - 1 - x } else { x };
+                    mask.set(mx, y, true);
+                }
+            }
+        }
+        mask
+    }
+
+    pub fn size(&self) -> Vec2i {
+        self.sz
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.sz.x || y >= self.sz.y {
+            return false;
+        }
+        let x = x as usize;
+        self.bits[y as usize * self.pitch + x / 64] & (1 << (x % 64)) != 0
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, on: bool) {
+        assert!(0 <= x && x < self.sz.x && 0 <= y && y < self.sz.y);
+        let x = x as usize;
+        let word = &mut self.bits[y as usize * self.pitch + x / 64];
+        if on {
+            *word |= 1 << (x % 64);
+        } else {
+            *word &= !(1 << (x % 64));
+        }
+    }
+
+    /// Up to 64 bits of row `y` starting at column `x`; bit 0 is column `x`
+    fn run(&self, x: usize, y: usize, len: usize) -> u64 {
+        let row = &self.bits[y * self.pitch..(y + 1) * self.pitch];
+        let (word, shift) = (x / 64, x % 64);
+        let mut bits = row[word] >> shift;
+        if shift > 0 && word + 1 < row.len() {
+            bits |= row[word + 1] << (64 - shift);
+        }
+        if len < 64 {
+            bits &= (1 << len) - 1;
+        }
+        bits
+    }
+
+    /// Whether this mask at `pos` and `other` at `other_pos` have a set pixel in common
+    pub fn overlaps(&self, pos: Vec2i, other: &Mask, other_pos: Vec2i) -> bool {
+        let a = Rect { pos, sz: self.sz };
+        let b = Rect {
+            pos: other_pos,
+            sz: other.sz,
+        };
+        if !a.overlaps(b) {
+            return false;
+        }
+        let x0 = pos.x.max(other_pos.x);
+        let x1 = (pos.x + self.sz.x).min(other_pos.x + other.sz.x);
+        let y0 = pos.y.max(other_pos.y);
+        let y1 = (pos.y + self.sz.y).min(other_pos.y + other.sz.y);
+        for y in y0..y1 {
+            let mut x = x0;
+            while x < x1 {
+                let len = ((x1 - x) as usize).min(64);
+                let mine = self.run((x - pos.x) as usize, (y - pos.y) as usize, len);
+                let theirs = other.run((x - other_pos.x) as usize, (y - other_pos.y) as usize, len);
+                if mine & theirs != 0 {
+                    return true;
+                }
+                x += len as i32;
+            }
+        }
+        false
+    }
+
+    /// Whether this mask at `pos` has a set pixel inside `rect`
+    pub fn overlaps_rect(&self, pos: Vec2i, rect: Rect) -> bool {
+        let a = Rect { pos, sz: self.sz };
+        if !a.overlaps(rect) {
+            return false;
+        }
+        let x0 = pos.x.max(rect.pos.x);
+        let x1 = (pos.x + self.sz.x).min(rect.pos.x + rect.sz.x);
+        let y0 = pos.y.max(rect.pos.y);
+        let y1 = (pos.y + self.sz.y).min(rect.bottom());
+        for y in y0..y1 {
+            let mut x = x0;
+            while x < x1 {
+                let len = ((x1 - x) as usize).min(64);
+                if self.run((x - pos.x) as usize, (y - pos.y) as usize, len) != 0 {
+                    return true;
+                }
+                x += len as i32;
+            }
+        }
+        false
+    }
+}
+
+/// Masks for every frame of an AnimationSet, both ways round
+pub struct SpriteMasks {
+    masks: HashMap<(Rect, bool), Mask>,
+}
+
+impl SpriteMasks {
+    pub fn new(set: &AnimationSet) -> Self {
+        let mut masks = HashMap::new();
+        for animation in set.animations.values() {
+            for &frame in &animation.frames {
+                for flip in [false, true] {
+                    masks
+                        .entry((frame, flip))
+                        .or_insert_with(|| Mask::from_alpha(&set.image, frame, flip));
+                }
+            }
+        }
+        Self { masks }
+    }
+
+    /// The mask of the frame a sprite is showing, if it's one of this set's
+    pub fn get(&self, sprite: &Sprite) -> Option<&Mask> {
+        self.masks.get(&(sprite.frame, sprite.flip))
+    }
+
+    /// Whether two sprites' drawn pixels touch; sprites without masks never do
+    pub fn sprites_overlap(&self, a: &Sprite, other: &SpriteMasks, b: &Sprite) -> bool {
+        if !a.visible || !b.visible {
+            return false;
+        }
+        match (self.get(a), other.get(b)) {
+            (Some(ma), Some(mb)) => ma.overlaps(a.draw_pos(), mb, b.draw_pos()),
+            _ => false,
+        }
+    }
+
+    /// Whether a sprite's drawn pixels touch `rect`
+    pub fn sprite_hits_rect(&self, sprite: &Sprite, rect: Rect) -> bool {
+        sprite.visible
+            && self
+                .get(sprite)
+                .is_some_and(|mask| mask.overlaps_rect(sprite.draw_pos(), rect))
+    }
+}
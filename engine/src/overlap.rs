@@ -0,0 +1,179 @@
+//! Telling things when they start and stop overlapping.
+//!
+//! Every body is on one or more layers and has a mask of the layers it cares about. Each
+//! tick compares this frame's overlapping pairs against last frame's and reports the
+//! difference, so a bullet hears about an enemy once, when it first touches it.
+
+use crate::raycast::{self, RayHit};
+use crate::spatial::SpatialHash;
+use crate::types::{Rect, Vec2i};
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// A set of layer bits
+pub type Layers = u32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverlapEvent<K> {
+    /// The first body's mask includes the second's layer; if both care about each other,
+    /// the smaller key comes first
+    Begin(K, K),
+    End(K, K),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Body {
+    layer: Layers,
+    mask: Layers,
+}
+
+pub struct Overlaps<K> {
+    grid: SpatialHash<K>,
+    bodies: HashMap<K, Body>,
+    touching: HashSet<(K, K)>,
+    /// Pairs that ended because a body was removed, reported on the next tick
+    ended: Vec<(K, K)>,
+}
+
+impl<K: Copy + Eq + Hash + Ord> Overlaps<K> {
+    pub fn new(cell_sz: Vec2i) -> Self {
+        Self {
+            grid: SpatialHash::new(cell_sz),
+            bodies: HashMap::new(),
+            touching: HashSet::new(),
+            ended: vec![],
+        }
+    }
+
+    /// Add a body on `layer` that wants to hear about bodies on any layer in `mask`
+    pub fn insert(&mut self, key: K, rect: Rect, layer: Layers, mask: Layers) {
+        self.bodies.insert(key, Body { layer, mask });
+        self.grid.insert(key, rect);
+    }
+
+    /// Move a body
+    pub fn update(&mut self, key: K, rect: Rect) {
+        self.grid.update(key, rect);
+    }
+
+    /// Take a body out; anything it was touching gets an End on the next tick
+    pub fn remove(&mut self, key: K) -> Option<Rect> {
+        self.bodies.remove(&key)?;
+        let mut ended: Vec<_> = self
+            .touching
+            .iter()
+            .filter(|(a, b)| *a == key || *b == key)
+            .copied()
+            .collect();
+        ended.sort();
+        for pair in &ended {
+            self.touching.remove(pair);
+        }
+        self.ended.extend(ended);
+        self.grid.remove(key)
+    }
+
+    pub fn contains(&self, key: K) -> bool {
+        self.bodies.contains_key(&key)
+    }
+
+    pub fn rect(&self, key: K) -> Option<Rect> {
+        self.grid.get(key)
+    }
+
+    /// Everything a body overlapped as of the last tick
+    pub fn touching(&self, key: K) -> Vec<K> {
+        let mut found: Vec<K> = self
+            .touching
+            .iter()
+            .filter_map(|&(a, b)| {
+                if a == key {
+                    Some(b)
+                } else if b == key {
+                    Some(a)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    /// The nearest body on any layer in `mask` that a ray from `from` along `dir` hits
+    /// within `max` pixels
+    pub fn raycast(
+        &self,
+        from: (f32, f32),
+        dir: (f32, f32),
+        max: f32,
+        mask: Layers,
+    ) -> Option<(K, RayHit)> {
+        let len = (dir.0 * dir.0 + dir.1 * dir.1).sqrt();
+        if len == 0.0 {
+            return None;
+        }
+        let to = (from.0 + dir.0 / len * max, from.1 + dir.1 / len * max);
+        let (x0, y0) = (
+            from.0.min(to.0).floor() as i32,
+            from.1.min(to.1).floor() as i32,
+        );
+        let (x1, y1) = (
+            from.0.max(to.0).ceil() as i32,
+            from.1.max(to.1).ceil() as i32,
+        );
+        let reach = Rect {
+            pos: Vec2i { x: x0, y: y0 },
+            sz: Vec2i {
+                x: x1 - x0 + 1,
+                y: y1 - y0 + 1,
+            },
+        };
+        self.grid
+            .query(reach)
+            .into_iter()
+            .filter(|key| self.bodies[key].layer & mask != 0)
+            .filter_map(|key| {
+                let hit = raycast::ray_rect(from, dir, max, self.grid.get(key)?)?;
+                Some((key, hit))
+            })
+            .min_by(|(ka, a), (kb, b)| a.distance.total_cmp(&b.distance).then(ka.cmp(kb)))
+    }
+
+    /// A pair in event order, if either side cares about the other
+    fn ordered(&self, a: K, b: K) -> Option<(K, K)> {
+        let (ba, bb) = (self.bodies[&a], self.bodies[&b]);
+        match (ba.mask & bb.layer != 0, bb.mask & ba.layer != 0) {
+            (true, false) => Some((a, b)),
+            (false, true) => Some((b, a)),
+            (true, true) => Some((a.min(b), a.max(b))),
+            (false, false) => None,
+        }
+    }
+
+    /// Find what's overlapping now and report what changed since the last tick:
+    /// ends first, then begins, each in key order
+    pub fn tick(&mut self) -> Vec<OverlapEvent<K>> {
+        let now: HashSet<(K, K)> = self
+            .grid
+            .pairs()
+            .into_iter()
+            .filter_map(|(a, b)| self.ordered(a, b))
+            .collect();
+
+        let mut ended = std::mem::take(&mut self.ended);
+        let mut gone: Vec<_> = self.touching.difference(&now).copied().collect();
+        gone.sort();
+        ended.extend(gone);
+        let mut begun: Vec<_> = now.difference(&self.touching).copied().collect();
+        begun.sort();
+
+        self.touching = now;
+        ended
+            .into_iter()
+            .map(|(a, b)| OverlapEvent::End(a, b))
+            .chain(begun.into_iter().map(|(a, b)| OverlapEvent::Begin(a, b)))
+            .collect()
+    }
+}
@@ -0,0 +1,242 @@
+//! A kinematic platformer character: running, jumping and falling through a Tilemap.
+//!
+//! Call `Platformer::update` once a frame with that frame's input; it handles gravity,
+//! acceleration, friction from the tile underfoot, and the forgiving bits players expect:
+//! coyote time, jump buffering, short hops and dropping through one-way platforms.
+//! With moving platforms, `ride` them first and then `update_among` their solids.
+
+use crate::collision::{self, Contacts, MoveOpts, Solid};
+use crate::platforms::{self, MovingPlatform};
+use crate::tiles::{TileShape, Tilemap};
+use crate::types::{Rect, Vec2i};
+
+/// How a Platformer moves; speeds are in pixels per frame
+#[derive(Clone, Copy, Debug)]
+pub struct PlatformerParams {
+    /// Added to vertical speed every frame
+    pub gravity: f32,
+    pub max_fall: f32,
+    /// Speed gained per frame while a direction is held
+    pub accel: f32,
+    pub max_speed: f32,
+    /// Speed lost per frame with no direction held; the ground tile's friction scales this
+    /// and accel
+    pub decel: f32,
+    /// Upward speed at the start of a jump
+    pub jump_speed: f32,
+    /// Letting go of jump while rising multiplies upward speed by this, so taps are short
+    /// hops; 1.0 makes every jump full height
+    pub jump_cut: f32,
+    /// Frames after walking off a ledge that jumping still works
+    pub coyote_frames: usize,
+    /// Frames a jump press is remembered before landing
+    pub jump_buffer_frames: usize,
+    /// How far walking can pull the body down onto a slope or step below it; leaving the top
+    /// of a 45° slope needs about half the body's width
+    pub snap_down: i32,
+}
+
+impl Default for PlatformerParams {
+    fn default() -> Self {
+        Self {
+            gravity: 0.2,
+            max_fall: 8.0,
+            accel: 0.2,
+            max_speed: 2.0,
+            decel: 0.1,
+            jump_speed: 5.0,
+            jump_cut: 0.5,
+            coyote_frames: 6,
+            jump_buffer_frames: 6,
+            snap_down: 8,
+        }
+    }
+}
+
+/// One frame of controls
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PlatformerInput {
+    pub left: bool,
+    pub right: bool,
+    /// Jump is held
+    pub jump: bool,
+    /// Jump went down this frame
+    pub jump_pressed: bool,
+    /// Down is held; with a jump, drops through one-way platforms
+    pub down: bool,
+}
+
+pub struct Platformer {
+    pub rect: Rect,
+    pub vx: f32,
+    pub vy: f32,
+    pub params: PlatformerParams,
+    /// What the body touched in the last update
+    pub contacts: Contacts,
+    /// Movement smaller than a pixel, carried over to the next frame
+    remainder: (f32, f32),
+    coyote: usize,
+    jump_buffer: usize,
+    /// Still rising from a jump, so letting go can cut it short
+    rising: bool,
+    /// The top of the one-way platform being dropped through
+    dropping: Option<i32>,
+}
+
+impl Platformer {
+    pub fn new(rect: Rect, params: PlatformerParams) -> Self {
+        Self {
+            rect,
+            vx: 0.0,
+            vy: 0.0,
+            params,
+            contacts: Contacts::default(),
+            remainder: (0.0, 0.0),
+            coyote: 0,
+            jump_buffer: 0,
+            rising: false,
+            dropping: None,
+        }
+    }
+
+    pub fn grounded(&self) -> bool {
+        self.contacts.ground
+    }
+
+    /// Move the body somewhere else, e.g. to respawn, forgetting its speed
+    pub fn teleport(&mut self, pos: Vec2i) {
+        self.rect.pos = pos;
+        self.vx = 0.0;
+        self.vy = 0.0;
+        self.remainder = (0.0, 0.0);
+        self.rising = false;
+        self.dropping = None;
+    }
+
+    /// Go along with the platform it's standing on and get pushed by any that run into it,
+    /// before `update_among` this frame. Returns whether it got crushed.
+    pub fn ride(&mut self, map: &Tilemap, platforms: &[MovingPlatform]) -> bool {
+        platforms::ride(map, platforms, self.contacts.ground_solid, &mut self.rect)
+    }
+
+    /// Run one frame of movement through `map`
+    pub fn update(&mut self, map: &Tilemap, input: &PlatformerInput) -> Contacts {
+        self.update_among(map, &[], input)
+    }
+
+    /// Run one frame of movement through `map` and `solids`
+    pub fn update_among(
+        &mut self,
+        map: &Tilemap,
+        solids: &[Solid],
+        input: &PlatformerInput,
+    ) -> Contacts {
+        let p = self.params;
+        let grounded = self.contacts.ground;
+        let ground_tile = self.contacts.ground_cell.map(|cell| map.tile_in_cell(cell));
+        let friction = ground_tile.map_or(1.0, |tile| tile.friction);
+
+        // Running
+        if input.left != input.right {
+            let dir = if input.left { -1.0 } else { 1.0 };
+            if self.vx * dir < p.max_speed {
+                self.vx = (self.vx + dir * p.accel * friction).clamp(-p.max_speed, p.max_speed);
+            }
+        } else {
+            let decel = p.decel * friction;
+            if self.vx.abs() <= decel {
+                self.vx = 0.0;
+            } else {
+                self.vx -= decel * self.vx.signum();
+            }
+        }
+
+        // Jumping
+        if grounded {
+            self.coyote = p.coyote_frames;
+        } else {
+            self.coyote = self.coyote.saturating_sub(1);
+        }
+        if input.jump_pressed {
+            self.jump_buffer = p.jump_buffer_frames.max(1);
+        }
+        let ground_solid = self.contacts.ground_solid.and_then(|i| solids.get(i));
+        let one_way_top = match (ground_tile, ground_solid) {
+            (Some(tile), _) if tile.shape == TileShape::OneWay => {
+                Some(map.cell_corner(self.contacts.ground_cell.unwrap()).y)
+            }
+            (None, Some(solid)) if solid.one_way => Some(solid.rect.pos.y),
+            _ => None,
+        };
+        if let Some(top) = one_way_top.filter(|_| self.jump_buffer > 0 && input.down) {
+            self.dropping = Some(top);
+            self.jump_buffer = 0;
+        } else if self.jump_buffer > 0 && (grounded || self.coyote > 0) {
+            self.vy = -p.jump_speed;
+            self.remainder.1 = 0.0;
+            self.rising = true;
+            self.coyote = 0;
+            self.jump_buffer = 0;
+        } else {
+            self.jump_buffer = self.jump_buffer.saturating_sub(1);
+        }
+        if self.rising && !input.jump && self.vy < 0.0 {
+            self.vy *= p.jump_cut;
+            self.rising = false;
+        }
+        if self.vy >= 0.0 {
+            self.rising = false;
+        }
+
+        self.vy = (self.vy + p.gravity).min(p.max_fall);
+
+        // Only move whole pixels, saving the rest for later
+        self.remainder.0 += self.vx;
+        self.remainder.1 += self.vy;
+        let delta = Vec2i {
+            x: self.remainder.0 as i32,
+            y: self.remainder.1 as i32,
+        };
+        self.remainder.0 -= delta.x as f32;
+        self.remainder.1 -= delta.y as f32;
+
+        let opts = MoveOpts {
+            drop_through: self.dropping.is_some(),
+        };
+        let mut contacts = collision::move_box_among(map, &mut self.rect, delta, opts, solids);
+
+        // Stay on the ground walking down slopes and steps instead of skipping off them
+        if grounded
+            && !contacts.ground
+            && !self.rising
+            && self.dropping.is_none()
+            && collision::snap_down(map, &mut self.rect, p.snap_down)
+        {
+            contacts.ground_cell = collision::ground_under(map, &self.rect);
+            contacts.ground = contacts.ground_cell.is_some();
+        }
+
+        if contacts.left || contacts.right {
+            self.vx = 0.0;
+            self.remainder.0 = 0.0;
+        }
+        if contacts.ceiling {
+            self.vy = 0.0;
+            self.remainder.1 = 0.0;
+            self.rising = false;
+        }
+        if contacts.ground && self.vy > 0.0 {
+            self.vy = 0.0;
+            self.remainder.1 = 0.0;
+        }
+        // Done dropping once the whole body is below the platform
+        if let Some(top) = self.dropping {
+            if self.rect.pos.y >= top {
+                self.dropping = None;
+            }
+        }
+
+        self.contacts = contacts;
+        contacts
+    }
+}
@@ -0,0 +1,175 @@
+//! Platforms that travel along a path and carry whatever stands on them.
+//!
+//! Step the platforms first each frame, then let every body ride them before it moves
+//! itself. A body standing on a platform goes along with it; a body a platform runs into
+//! gets pushed, and one that can't be pushed out of the way is crushed.
+
+use crate::collision::{self, MoveOpts, Solid};
+use crate::tiles::Tilemap;
+use crate::types::{Rect, Vec2i};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PathMode {
+    /// Back to the first point after the last
+    Loop,
+    /// Back and forth along the points
+    PingPong,
+}
+
+#[derive(Clone, Debug)]
+pub struct MovingPlatform {
+    pub rect: Rect,
+    /// Only solid from above, like a one-way tile
+    pub one_way: bool,
+    /// Where its top-left corner travels between
+    pub points: Vec<Vec2i>,
+    /// Pixels per frame
+    pub speed: f32,
+    pub mode: PathMode,
+    /// Frames to stop for at each point
+    pub pause: usize,
+    /// How far it moved in the last step
+    pub delta: Vec2i,
+    /// Where it is, sub-pixel
+    pos: (f32, f32),
+    /// The index of the point it's heading for
+    target: usize,
+    /// Heading back down the points in PingPong mode
+    backwards: bool,
+    waiting: usize,
+}
+
+impl MovingPlatform {
+    /// A platform of size `sz` starting at the first of `points`
+    pub fn new(sz: Vec2i, points: Vec<Vec2i>, speed: f32, mode: PathMode) -> Self {
+        assert!(!points.is_empty(), "a platform needs somewhere to be");
+        let start = points[0];
+        Self {
+            rect: Rect { pos: start, sz },
+            one_way: false,
+            target: 1 % points.len(),
+            points,
+            speed,
+            mode,
+            pause: 0,
+            delta: Vec2i { x: 0, y: 0 },
+            pos: (start.x as f32, start.y as f32),
+            backwards: false,
+            waiting: 0,
+        }
+    }
+
+    pub fn solid(&self) -> Solid {
+        Solid {
+            rect: self.rect,
+            one_way: self.one_way,
+        }
+    }
+
+    fn next_target(&mut self) -> usize {
+        let (t, n) = (self.target, self.points.len());
+        match self.mode {
+            PathMode::Loop => (t + 1) % n,
+            PathMode::PingPong if self.backwards && t == 0 => {
+                self.backwards = false;
+                1
+            }
+            PathMode::PingPong if self.backwards => t - 1,
+            PathMode::PingPong if t + 1 == n => {
+                self.backwards = true;
+                t - 1
+            }
+            PathMode::PingPong => t + 1,
+        }
+    }
+
+    /// Move one frame along the path
+    pub fn step(&mut self) {
+        self.delta = Vec2i { x: 0, y: 0 };
+        if self.points.len() < 2 {
+            return;
+        }
+        if self.waiting > 0 {
+            self.waiting -= 1;
+            return;
+        }
+        let goal = self.points[self.target];
+        let (dx, dy) = (goal.x as f32 - self.pos.0, goal.y as f32 - self.pos.1);
+        let dist = (dx * dx + dy * dy).sqrt();
+        if dist <= self.speed {
+            self.pos = (goal.x as f32, goal.y as f32);
+            self.waiting = self.pause;
+            self.target = self.next_target();
+        } else {
+            self.pos.0 += dx / dist * self.speed;
+            self.pos.1 += dy / dist * self.speed;
+        }
+        let now = Vec2i {
+            x: self.pos.0.round() as i32,
+            y: self.pos.1.round() as i32,
+        };
+        self.delta = Vec2i {
+            x: now.x - self.rect.pos.x,
+            y: now.y - self.rect.pos.y,
+        };
+        self.rect.pos = now;
+    }
+}
+
+/// The platforms as Solids, in the same order, for `collision::move_box_among`
+pub fn solids(platforms: &[MovingPlatform]) -> Vec<Solid> {
+    platforms.iter().map(MovingPlatform::solid).collect()
+}
+
+/// Carry a box standing on `platforms[on]` along with the platform's last step, then push
+/// it out of any solid platform that moved into it. Returns whether it was crushed: stuck
+/// inside a platform with a wall or another platform in the way.
+pub fn ride(
+    map: &Tilemap,
+    platforms: &[MovingPlatform],
+    on: Option<usize>,
+    rect: &mut Rect,
+) -> bool {
+    let all = solids(platforms);
+    let except = |skip: usize| -> Vec<Solid> {
+        all.iter()
+            .enumerate()
+            .filter(|&(i, _)| i != skip)
+            .map(|(_, s)| *s)
+            .collect()
+    };
+
+    if let Some(i) = on {
+        let delta = platforms[i].delta;
+        collision::move_box_among(map, rect, delta, MoveOpts::default(), &except(i));
+    }
+
+    let mut crushed = false;
+    for (i, p) in platforms.iter().enumerate() {
+        if p.one_way || !p.rect.overlaps(*rect) {
+            continue;
+        }
+        // Out whichever side it was moving towards, along the axis that's the shortest way
+        let d = p.delta;
+        let push_x = match d.x.signum() {
+            1 => p.rect.pos.x + p.rect.sz.x - rect.pos.x,
+            -1 => p.rect.pos.x - (rect.pos.x + rect.sz.x),
+            _ => 0,
+        };
+        let push_y = match d.y.signum() {
+            1 => p.rect.bottom() - rect.pos.y,
+            -1 => p.rect.pos.y - rect.bottom(),
+            _ => 0,
+        };
+        let push = if push_x != 0 && (push_y == 0 || push_x.abs() <= push_y.abs()) {
+            Vec2i { x: push_x, y: 0 }
+        } else {
+            Vec2i { x: 0, y: push_y }
+        };
+        collision::move_box_among(map, rect, push, MoveOpts::default(), &except(i));
+        if p.rect.overlaps(*rect) {
+            crushed = true;
+        }
+    }
+    crushed
+}
@@ -0,0 +1,238 @@
+//! Things that fly and bounce: balls, grenades, thrown rocks.
+//!
+//! A Projectile is a circle with a float position. Tiles stop it using the same swept
+//! collision as everything else, and it can also bounce off free-standing obstacles like a
+//! hoop's rim and backboard. Stepping a copy ahead of time predicts where it will go.
+
+use crate::collision::{self, Contacts};
+use crate::tiles::Tilemap;
+use crate::types::{Rect, Vec2i};
+
+/// How a Projectile flies and bounces; speeds are in pixels per frame
+#[derive(Clone, Copy, Debug)]
+pub struct ProjectileParams {
+    /// Added to vertical speed every frame
+    pub gravity: f32,
+    /// Fraction of speed lost to the air every frame
+    pub drag: f32,
+    /// Fraction of speed kept bouncing straight off something
+    pub restitution: f32,
+    /// Fraction of speed along a surface kept when touching it
+    pub friction: f32,
+    /// Bounces slower than this don't bounce, and a projectile on the ground slower than
+    /// this comes to rest
+    pub rest_speed: f32,
+}
+
+impl Default for ProjectileParams {
+    fn default() -> Self {
+        Self {
+            gravity: 0.2,
+            drag: 0.0,
+            restitution: 0.6,
+            friction: 0.9,
+            rest_speed: 0.5,
+        }
+    }
+}
+
+/// Something a projectile bounces off that isn't part of a Tilemap
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Obstacle {
+    /// e.g. the front of a rim
+    Circle { center: (f32, f32), radius: f32 },
+    /// e.g. a backboard
+    Rect(Rect),
+}
+
+impl Obstacle {
+    /// The point of the obstacle nearest to `p`, and how far its surface is out from there
+    fn nearest(&self, (px, py): (f32, f32)) -> ((f32, f32), f32) {
+        match *self {
+            Obstacle::Circle { center, radius } => (center, radius),
+            Obstacle::Rect(r) => {
+                let x = px.clamp(r.pos.x as f32, (r.pos.x + r.sz.x) as f32);
+                let y = py.clamp(r.pos.y as f32, r.bottom() as f32);
+                ((x, y), 0.0)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Projectile {
+    /// The centre
+    pub pos: (f32, f32),
+    pub vel: (f32, f32),
+    pub radius: f32,
+    pub params: ProjectileParams,
+    /// What it touched in the last step
+    pub contacts: Contacts,
+    /// Lying still on the ground; stepping does nothing until it's given a velocity
+    pub resting: bool,
+    /// Where it was before the last step
+    prev: (f32, f32),
+}
+
+impl Projectile {
+    pub fn new(pos: (f32, f32), vel: (f32, f32), radius: f32, params: ProjectileParams) -> Self {
+        Self {
+            pos,
+            vel,
+            radius,
+            params,
+            contacts: Contacts::default(),
+            resting: false,
+            prev: pos,
+        }
+    }
+
+    /// Send it off again from where it is
+    pub fn launch(&mut self, vel: (f32, f32)) {
+        self.vel = vel;
+        self.resting = false;
+    }
+
+    /// The box around it, for drawing and tile collision
+    pub fn rect(&self) -> Rect {
+        let sz = (self.radius * 2.0).round().max(1.0) as i32;
+        Rect {
+            pos: Vec2i {
+                x: (self.pos.0 - self.radius).round() as i32,
+                y: (self.pos.1 - self.radius).round() as i32,
+            },
+            sz: Vec2i { x: sz, y: sz },
+        }
+    }
+
+    /// Fly for one frame, bouncing off solid tiles and `obstacles`
+    pub fn step(&mut self, map: &Tilemap, obstacles: &[Obstacle]) -> Contacts {
+        self.prev = self.pos;
+        if self.resting {
+            return self.contacts;
+        }
+        let p = self.params;
+        self.vel.1 += p.gravity;
+        self.vel.0 *= 1.0 - p.drag;
+        self.vel.1 *= 1.0 - p.drag;
+
+        // Move in pieces no longer than the radius, so thin obstacles can't be skipped over
+        let speed = (self.vel.0 * self.vel.0 + self.vel.1 * self.vel.1).sqrt();
+        let pieces = (speed / self.radius.max(1.0)).ceil().max(1.0) as usize;
+        let mut contacts = Contacts::default();
+        for _ in 0..pieces {
+            let hit = self.move_through(map, pieces as f32);
+            contacts.ground |= hit.ground;
+            contacts.ceiling |= hit.ceiling;
+            contacts.left |= hit.left;
+            contacts.right |= hit.right;
+            contacts.embedded = hit.embedded;
+            contacts.ground_cell = hit.ground_cell;
+            for obstacle in obstacles {
+                self.bounce_off(obstacle);
+            }
+        }
+
+        if contacts.ground && self.vel.1.abs() < p.rest_speed && self.vel.0.abs() < p.rest_speed {
+            self.vel = (0.0, 0.0);
+            self.resting = true;
+        }
+        self.contacts = contacts;
+        contacts
+    }
+
+    /// Move a `1/pieces` share of this frame's velocity through the tiles
+    fn move_through(&mut self, map: &Tilemap, pieces: f32) -> Contacts {
+        let p = self.params;
+        let target = (
+            self.pos.0 + self.vel.0 / pieces,
+            self.pos.1 + self.vel.1 / pieces,
+        );
+        let mut rect = self.rect();
+        let start = rect.pos;
+        let delta = Vec2i {
+            x: (target.0 - self.radius).round() as i32 - start.x,
+            y: (target.1 - self.radius).round() as i32 - start.y,
+        };
+        let contacts = collision::move_box(map, &mut rect, delta);
+
+        if contacts.left || contacts.right {
+            self.pos.0 += (rect.pos.x - start.x) as f32;
+            self.vel.0 = bounce(self.vel.0, p);
+            self.vel.1 *= p.friction;
+        } else {
+            self.pos.0 = target.0;
+        }
+        if contacts.ceiling || (contacts.ground && self.vel.1 > 0.0) {
+            self.pos.1 += (rect.pos.y - start.y) as f32;
+            self.vel.1 = bounce(self.vel.1, p);
+            self.vel.0 *= p.friction;
+        } else {
+            self.pos.1 = target.1;
+        }
+        contacts
+    }
+
+    fn bounce_off(&mut self, obstacle: &Obstacle) {
+        let ((nx, ny), reach) = obstacle.nearest(self.pos);
+        let (dx, dy) = (self.pos.0 - nx, self.pos.1 - ny);
+        let dist = (dx * dx + dy * dy).sqrt();
+        let min = self.radius + reach;
+        if dist >= min {
+            return;
+        }
+        // Dead centre on it: push back the way we came
+        let (ux, uy) = if dist > 0.0 {
+            (dx / dist, dy / dist)
+        } else {
+            let speed = (self.vel.0 * self.vel.0 + self.vel.1 * self.vel.1).sqrt();
+            if speed > 0.0 {
+                (-self.vel.0 / speed, -self.vel.1 / speed)
+            } else {
+                (0.0, -1.0)
+            }
+        };
+        self.pos = (nx + ux * min, ny + uy * min);
+        let into = self.vel.0 * ux + self.vel.1 * uy;
+        if into < 0.0 {
+            let kept = bounce(into, self.params);
+            self.vel.0 += (kept - into) * ux;
+            self.vel.1 += (kept - into) * uy;
+        }
+    }
+
+    /// Whether the centre passed down through the line at height `y` between `x0` and `x1`
+    /// in the last step, e.g. through a hoop
+    pub fn crossed_down(&self, y: f32, x0: f32, x1: f32) -> bool {
+        if !(self.prev.1 < y && self.pos.1 >= y) {
+            return false;
+        }
+        let t = (y - self.prev.1) / (self.pos.1 - self.prev.1);
+        let x = self.prev.0 + (self.pos.0 - self.prev.0) * t;
+        x0 <= x && x <= x1
+    }
+
+    /// Where the centre will be after each of the next `frames` steps, stopping early if it
+    /// comes to rest
+    pub fn predict(&self, map: &Tilemap, obstacles: &[Obstacle], frames: usize) -> Vec<(f32, f32)> {
+        let mut ghost = self.clone();
+        let mut path = Vec::with_capacity(frames);
+        for _ in 0..frames {
+            ghost.step(map, obstacles);
+            path.push(ghost.pos);
+            if ghost.resting {
+                break;
+            }
+        }
+        path
+    }
+}
+
+/// Speed after bouncing straight back off something, which is nothing if it was slow
+fn bounce(v: f32, p: ProjectileParams) -> f32 {
+    if v.abs() * p.restitution < p.rest_speed {
+        0.0
+    } else {
+        -v * p.restitution
+    }
+}
@@ -0,0 +1,241 @@
+//! Casting rays and segments through Tilemaps and against rects.
+//!
+//! Tilemap casts walk the grid one cell at a time in the order the ray crosses them, so
+//! they cost as much as the cells passed through, not the size of the map. Rays are
+//! stopped by the same shapes that stop moving boxes; one-way platforms let them through.
+
+use crate::collision::solid_rect;
+use crate::tiles::{TileShape, Tilemap};
+use crate::types::Rect;
+
+/// Where a ray stopped
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RayHit {
+    pub point: (f32, f32),
+    /// Unit vector out of the surface hit; (0, 0) if the ray started inside it
+    pub normal: (f32, f32),
+    /// How far along the ray, in pixels
+    pub distance: f32,
+    /// The tile's cell, for hits on a Tilemap
+    pub cell: Option<(i32, i32)>,
+}
+
+fn normalize((x, y): (f32, f32)) -> Option<(f32, f32)> {
+    let len = (x * x + y * y).sqrt();
+    (len > 0.0).then_some((x / len, y / len))
+}
+
+fn at(from: (f32, f32), dir: (f32, f32), t: f32) -> (f32, f32) {
+    (from.0 + dir.0 * t, from.1 + dir.1 * t)
+}
+
+/// Where a ray with unit `dir` enters `rect` between `t0` and `t1`, with the normal of
+/// the side it came through. A ray already inside at `t0` hits there with no normal.
+fn slab(
+    from: (f32, f32),
+    dir: (f32, f32),
+    rect: Rect,
+    t0: f32,
+    t1: f32,
+) -> Option<(f32, (f32, f32))> {
+    let (mut enter, mut exit) = (f32::NEG_INFINITY, f32::INFINITY);
+    let mut normal = (0.0, 0.0);
+    let axes = [
+        (
+            from.0,
+            dir.0,
+            rect.pos.x as f32,
+            (rect.pos.x + rect.sz.x) as f32,
+            ((-1.0, 0.0), (1.0, 0.0)),
+        ),
+        (
+            from.1,
+            dir.1,
+            rect.pos.y as f32,
+            rect.bottom() as f32,
+            ((0.0, -1.0), (0.0, 1.0)),
+        ),
+    ];
+    for (o, d, lo, hi, (lo_side, hi_side)) in axes {
+        if d == 0.0 {
+            if o < lo || o >= hi {
+                return None;
+            }
+            continue;
+        }
+        let (mut near, mut far) = ((lo - o) / d, (hi - o) / d);
+        // The side facing the ray is the one it enters through
+        let mut side = lo_side;
+        if near > far {
+            std::mem::swap(&mut near, &mut far);
+            side = hi_side;
+        }
+        if near > enter {
+            enter = near;
+            normal = side;
+        }
+        exit = exit.min(far);
+    }
+    if enter > exit || exit < t0 || enter > t1 {
+        return None;
+    }
+    // A little slack, since a ray walking the grid enters cells right on their edges
+    if enter < t0 - 1e-3 {
+        return Some((t0, (0.0, 0.0)));
+    }
+    Some((enter.max(t0), normal))
+}
+
+/// Cast a ray from `from` along `dir` (any length) against `rect`, up to `max` pixels
+pub fn ray_rect(from: (f32, f32), dir: (f32, f32), max: f32, rect: Rect) -> Option<RayHit> {
+    let dir = normalize(dir)?;
+    let (t, normal) = slab(from, dir, rect, 0.0, max)?;
+    Some(RayHit {
+        point: at(from, dir, t),
+        normal,
+        distance: t,
+        cell: None,
+    })
+}
+
+/// Where a ray crossing a slope tile's cell between `t0` and `t1` meets the slope
+fn slope_hit(
+    from: (f32, f32),
+    dir: (f32, f32),
+    (left, right): (f32, f32),
+    cell: Rect,
+    t0: f32,
+    t1: f32,
+) -> Option<(f32, (f32, f32))> {
+    let (w, h) = (cell.sz.x as f32, cell.sz.y as f32);
+    let x0 = cell.pos.x as f32;
+    let y0 = cell.bottom() as f32 - left * h;
+    let m = -(right - left) * h / w;
+    // How far below the surface a point is; the tile is solid where this is positive
+    let depth = |t: f32| {
+        let (x, y) = at(from, dir, t);
+        y - (y0 + m * (x - x0))
+    };
+    let (d0, d1) = (depth(t0), depth(t1));
+    if d0 >= 0.0 {
+        // Came in through the side or bottom of the solid part
+        let (_, normal) = slab(from, dir, cell, t0, t1)?;
+        return Some((t0, normal));
+    }
+    if d1 < 0.0 {
+        return None;
+    }
+    let t = t0 + (t1 - t0) * (-d0 / (d1 - d0));
+    Some((t, normalize((m, -1.0)).unwrap()))
+}
+
+/// Cast a ray from `from` along `dir` (any length) through the solid tiles of `map`,
+/// up to `max` pixels
+pub fn ray_tiles(map: &Tilemap, from: (f32, f32), dir: (f32, f32), max: f32) -> Option<RayHit> {
+    let dir = normalize(dir)?;
+    let tsz = map.tileset().tile_sz();
+    let (tw, th) = (tsz.x as f32, tsz.y as f32);
+    let (ox, oy) = (map.position.x as f32, map.position.y as f32);
+    let (w, h) = map.size();
+
+    let mut cell = (
+        ((from.0 - ox) / tw).floor() as i32,
+        ((from.1 - oy) / th).floor() as i32,
+    );
+    let sign = |d: f32| {
+        if d > 0.0 {
+            1
+        } else if d < 0.0 {
+            -1
+        } else {
+            0
+        }
+    };
+    let step = (sign(dir.0), sign(dir.1));
+    // How far along the ray the next vertical and horizontal grid lines are, and how far
+    // apart they are
+    let next_line = |c: i32, o: f32, sz: f32, d: f32, p: f32| {
+        if d > 0.0 {
+            (o + (c + 1) as f32 * sz - p) / d
+        } else if d < 0.0 {
+            (o + c as f32 * sz - p) / d
+        } else {
+            f32::INFINITY
+        }
+    };
+    let mut next = (
+        next_line(cell.0, ox, tw, dir.0, from.0),
+        next_line(cell.1, oy, th, dir.1, from.1),
+    );
+    let delta = (
+        if dir.0 != 0.0 {
+            tw / dir.0.abs()
+        } else {
+            f32::INFINITY
+        },
+        if dir.1 != 0.0 {
+            th / dir.1.abs()
+        } else {
+            f32::INFINITY
+        },
+    );
+
+    let mut t = 0.0;
+    while t <= max {
+        let t_exit = next.0.min(next.1).min(max);
+        let tile = map.tile_in_cell(cell);
+        let bounds = Rect {
+            pos: map.cell_corner(cell),
+            sz: tsz,
+        };
+        let hit = match tile.shape {
+            TileShape::Full | TileShape::Half => {
+                let solid = solid_rect(tile, bounds.pos, tsz).unwrap();
+                slab(from, dir, solid, t, t_exit)
+            }
+            TileShape::Slope { left, right } => {
+                slope_hit(from, dir, (left, right), bounds, t, t_exit)
+            }
+            TileShape::OneWay | TileShape::None => None,
+        };
+        if let Some((t, normal)) = hit {
+            return Some(RayHit {
+                point: at(from, dir, t),
+                normal,
+                distance: t,
+                cell: Some(cell),
+            });
+        }
+
+        // Everything past the edge of the map is the same tile, so once the ray is out
+        // there heading away, nothing else can stop it
+        let outside_x = (cell.0 < 0 && step.0 <= 0) || (cell.0 >= w as i32 && step.0 >= 0);
+        let outside_y = (cell.1 < 0 && step.1 <= 0) || (cell.1 >= h as i32 && step.1 >= 0);
+        if outside_x || outside_y {
+            return None;
+        }
+
+        if next.0 < next.1 {
+            t = next.0;
+            next.0 += delta.0;
+            cell.0 += step.0;
+        } else {
+            t = next.1;
+            next.1 += delta.1;
+            cell.1 += step.1;
+        }
+    }
+    None
+}
+
+/// Cast the segment from `from` to `to` through the solid tiles of `map`
+pub fn segment_tiles(map: &Tilemap, from: (f32, f32), to: (f32, f32)) -> Option<RayHit> {
+    let dir = (to.0 - from.0, to.1 - from.1);
+    let len = (dir.0 * dir.0 + dir.1 * dir.1).sqrt();
+    ray_tiles(map, from, dir, len)
+}
+
+/// Whether nothing solid lies between two points
+pub fn line_of_sight(map: &Tilemap, from: (f32, f32), to: (f32, f32)) -> bool {
+    segment_tiles(map, from, to).is_none()
+}
@@ -0,0 +1,164 @@
+//! A grid of buckets for finding which things overlap without checking every pair.
+//!
+//! Each thing is filed under every cell its rect touches, so a query only has to look at
+//! the things in the cells it touches. Cells a bit bigger than the things in them work best.
+
+use crate::types::{Rect, Vec2i};
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+pub struct SpatialHash<K> {
+    cell_sz: Vec2i,
+    cells: HashMap<(i32, i32), Vec<K>>,
+    rects: HashMap<K, Rect>,
+}
+
+impl<K: Copy + Eq + Hash> SpatialHash<K> {
+    pub fn new(cell_sz: Vec2i) -> Self {
+        assert!(cell_sz.x > 0 && cell_sz.y > 0, "cells need some size");
+        Self {
+            cell_sz,
+            cells: HashMap::new(),
+            rects: HashMap::new(),
+        }
+    }
+
+    /// The first and last cells a rect touches; empty rects still touch the cell they're in
+    fn cell_range(&self, rect: Rect) -> ((i32, i32), (i32, i32)) {
+        let x1 = rect.pos.x + (rect.sz.x - 1).max(0);
+        let y1 = rect.pos.y + (rect.sz.y - 1).max(0);
+        (
+            (
+                rect.pos.x.div_euclid(self.cell_sz.x),
+                rect.pos.y.div_euclid(self.cell_sz.y),
+            ),
+            (x1.div_euclid(self.cell_sz.x), y1.div_euclid(self.cell_sz.y)),
+        )
+    }
+
+    fn cells_of(&self, rect: Rect) -> impl Iterator<Item = (i32, i32)> {
+        let ((x0, y0), (x1, y1)) = self.cell_range(rect);
+        (y0..=y1).flat_map(move |y| (x0..=x1).map(move |x| (x, y)))
+    }
+
+    fn file(&mut self, key: K, rect: Rect) {
+        for cell in self.cells_of(rect).collect::<Vec<_>>() {
+            self.cells.entry(cell).or_default().push(key);
+        }
+    }
+
+    fn unfile(&mut self, key: K, rect: Rect) {
+        for cell in self.cells_of(rect).collect::<Vec<_>>() {
+            if let Some(bucket) = self.cells.get_mut(&cell) {
+                bucket.retain(|k| *k != key);
+                if bucket.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    /// Add a thing, or move it if it's already here
+    pub fn insert(&mut self, key: K, rect: Rect) {
+        match self.rects.get(&key) {
+            Some(_) => self.update(key, rect),
+            None => {
+                self.rects.insert(key, rect);
+                self.file(key, rect);
+            }
+        }
+    }
+
+    /// Move a thing that's already here; it's only refiled if it changed cells
+    pub fn update(&mut self, key: K, rect: Rect) {
+        let old = *self
+            .rects
+            .get(&key)
+            .expect("updating something that isn't in the spatial hash");
+        if self.cell_range(old) != self.cell_range(rect) {
+            self.unfile(key, old);
+            self.file(key, rect);
+        }
+        self.rects.insert(key, rect);
+    }
+
+    pub fn remove(&mut self, key: K) -> Option<Rect> {
+        let rect = self.rects.remove(&key)?;
+        self.unfile(key, rect);
+        Some(rect)
+    }
+
+    pub fn get(&self, key: K) -> Option<Rect> {
+        self.rects.get(&key).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.rects.clear();
+    }
+
+    /// Everything whose rect shares some area with `rect`
+    pub fn query(&self, rect: Rect) -> Vec<K> {
+        let mut seen = HashSet::new();
+        let mut found = vec![];
+        for cell in self.cells_of(rect) {
+            for &key in self.cells.get(&cell).into_iter().flatten() {
+                if seen.insert(key) && self.rects[&key].overlaps(rect) {
+                    found.push(key);
+                }
+            }
+        }
+        found
+    }
+
+    /// Everything whose rect contains `point`, edges included
+    pub fn query_point(&self, point: Vec2i) -> Vec<K> {
+        let cell = (
+            point.x.div_euclid(self.cell_sz.x),
+            point.y.div_euclid(self.cell_sz.y),
+        );
+        // A point on a rect's far edge is in the next cell over, which the rect isn't
+        // filed under, so look one cell back as well
+        let mut seen = HashSet::new();
+        let mut found = vec![];
+        for dy in -1..=0 {
+            for dx in -1..=0 {
+                let bucket = self.cells.get(&(cell.0 + dx, cell.1 + dy));
+                for &key in bucket.into_iter().flatten() {
+                    if seen.insert(key) && self.rects[&key].contains_point(point) {
+                        found.push(key);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// Every pair of things that overlap, each pair once
+    pub fn pairs(&self) -> Vec<(K, K)> {
+        let mut seen = HashSet::new();
+        let mut found = vec![];
+        for bucket in self.cells.values() {
+            for (i, &a) in bucket.iter().enumerate() {
+                for &b in &bucket[i + 1..] {
+                    if seen.insert((a, b))
+                        && seen.insert((b, a))
+                        && self.rects[&a].overlaps(self.rects[&b])
+                    {
+                        found.push((a, b));
+                    }
+                }
+            }
+        }
+        found
+    }
+}
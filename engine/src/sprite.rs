@@ -1,73 +1,162 @@
-use crate::animations::AnimationState;
-use crate::types::Rect;
-
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
-pub enum Character {
-    Mario,
-    Luigi,
-    SpaceInvader,
-    SpaceInvaderEnemy1,
-    SpaceInvaderEnemy2,
+use crate::animations::{AnimationSet, AnimationState};
+use crate::types::{Image, Rect, Vec2i};
+
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+// Leak each distinct name once so ids can be Copy and compared cheaply
+fn intern(name: &str) -> &'static str {
+    static NAMES: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+    let mut names = NAMES.lock().unwrap();
+    let names = names.get_or_insert_with(HashSet::new);
+    if let Some(interned) = names.get(name) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(name.to_owned().into_boxed_str());
+    names.insert(interned);
+    interned
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
-pub enum Action {
-    Walk,
-    Die,
-    Jump,
-    Stand,
-    Glide,
+macro_rules! name_id {
+    ($(#[$meta:meta])* $id:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+        pub struct $id(&'static str);
+
+        impl $id {
+            /// For names known at compile time, e.g. in a `const`
+            pub const fn named(name: &'static str) -> Self {
+                Self(name)
+            }
+
+            /// For names read at runtime, e.g. from an atlas file
+            pub fn new(name: &str) -> Self {
+                Self(intern(name))
+            }
+
+            pub fn name(&self) -> &'static str {
+                self.0
+            }
+        }
+
+        impl std::fmt::Display for $id {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.0)
+            }
+        }
+    };
 }
 
+name_id!(
+    /// Who a sprite is; each game defines its own, e.g. `Character::named("mario")`
+    Character
+);
+
+name_id!(
+    /// What a sprite is doing, used to pick its animation
+    Action
+);
+
+// Common actions, so engine code like the platformer transitions can refer to them.
+// Games are free to define more.
+#[allow(non_upper_case_globals)]
 impl Action {
-    #[allow(dead_code)]
-    pub fn turn(&self) -> Self {
-        use Action::*;
-        match *self {
-            Walk => Jump,
-            Jump => Die,
-            Die => Stand,
-            Stand => Glide,
-            Glide => Walk,
-        }
-    }
+    pub const Walk: Action = Action::named("walk");
+    pub const Die: Action = Action::named("die");
+    pub const Jump: Action = Action::named("jump");
+    pub const Stand: Action = Action::named("stand");
+    pub const Glide: Action = Action::named("glide");
 }
 
-// pub trait DrawSpriteExt {
-//     fn draw_sprite(&mut self, s: &Sprite, pos: Vec2i);
-// }
+pub trait DrawSpriteExt {
+    fn draw_sprite(&mut self, s: &Sprite);
+}
 
-// use crate::image::Image;
-// impl DrawSpriteExt for Image {
-//     fn draw_sprite(&mut self, s: &Sprite, pos: Vec2i) {
-//         self.bitblt(&s.character, s.animation_state.current_frame(), pos);
-//     }
-// }
+impl DrawSpriteExt for Image {
+    fn draw_sprite(&mut self, s: &Sprite) {
+        if s.visible {
+            self.bitblt(&s.image, s.frame, s.draw_pos(), s.flip);
+        }
+    }
+}
 
-#[allow(dead_code)]
 pub struct Sprite {
     pub character: Character,
     pub action: Action,
     pub animation_state: AnimationState,
+    /// Where the sprite is in the world and how big it is
     pub shape: Rect,
+    /// The sheet this sprite's frames come from
+    pub image: Rc<Image>,
+    /// The frame shown by the last animation tick
+    pub frame: Rect,
+    /// Facing left; frames are mirrored when drawn
+    pub flip: bool,
+    /// Bigger layers draw on top
+    pub layer: i32,
+    pub visible: bool,
+    /// Anchor point as a fraction of the size: (0.0, 0.0) is the top left,
+    /// (0.5, 1.0) the bottom centre. The frame's pivot is placed on the shape's
+    /// pivot, so frames of different sizes stay put.
+    pub pivot: (f32, f32),
 }
 
 impl Sprite {
-    #[allow(dead_code)]
-    pub fn play_animation(&mut self, speedup_factor: &usize) -> Rect {
-        self.animation_state.tick(speedup_factor) // you can use types to choose which animations to play
+    pub fn new(animation_set: &AnimationSet, action: Action, pos: Vec2i) -> Self {
+        let animation_state = animation_set.play_animation(action);
+        let frame = animation_state.animation.initial_frame();
+        Self {
+            character: animation_set.character,
+            action,
+            animation_state,
+            shape: Rect { pos, sz: frame.sz },
+            image: animation_set.image.clone(),
+            frame,
+            flip: false,
+            layer: 0,
+            visible: true,
+            pivot: (0.0, 0.0),
+        }
     }
 
-    pub fn turn_action(&mut self) {
-        self.action = self.action.turn();
+    pub fn play_animation(&mut self, speedup_factor: &usize) -> Rect {
+        self.tick_animation(speedup_factor);
+        self.frame
     }
+
     pub fn set_animation(&mut self, animation_state: AnimationState) {
         self.action = animation_state.action;
+        self.frame = animation_state.animation.initial_frame();
         self.animation_state = animation_state;
     }
 
-    #[allow(dead_code)]
-    fn draw() {}
-    #[allow(dead_code)]
-    fn tick_animation() {}
+    pub fn tick_animation(&mut self, speedup_factor: &usize) {
+        self.frame = self.animation_state.tick(speedup_factor);
+    }
+
+    /// Top left corner to blit the current frame at
+    pub fn draw_pos(&self) -> Vec2i {
+        let (px, py) = self.pivot;
+        // Facing the other way mirrors the pivot too
+        let fpx = if self.flip { 1.0 - px } else { px };
+        Vec2i {
+            x: self.shape.pos.x + (self.shape.sz.x as f32 * fpx) as i32
+                - (self.frame.sz.x as f32 * fpx) as i32,
+            y: self.shape.pos.y + (self.shape.sz.y as f32 * py) as i32
+                - (self.frame.sz.y as f32 * py) as i32,
+        }
+    }
+
+    /// Where the current frame lands in the world
+    pub fn frame_rect(&self) -> Rect {
+        Rect {
+            pos: self.draw_pos(),
+            sz: self.frame.sz,
+        }
+    }
+
+    pub fn draw(&self, fb: &mut Image) {
+        fb.draw_sprite(self);
+    }
 }
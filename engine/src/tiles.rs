@@ -1,56 +1,321 @@
+use crate::camera::Camera;
+use crate::draw::{self, DrawCmd, DrawKind, DrawList};
 use crate::types::{Image, Rect, Vec2i};
 
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
 
-pub const TILE_SZ: usize = 16;
+pub mod autotile;
+pub mod tiled;
 
-/// A graphical tile, we'll implement Copy since it's tiny
-#[derive(Clone, Copy)]
+/// Tilemaps are drawn in square chunks this many tiles across
+pub const CHUNK_TILES: usize = 8;
+
+/// How a tile collides with things moving through it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TileShape {
+    /// Nothing stops here
+    None,
+    /// The whole tile
+    Full,
+    /// The bottom half of the tile
+    Half,
+    /// A floor whose height (as a fraction of the tile, from the bottom) goes from
+    /// `left` at the tile's left edge to `right` at its right edge
+    Slope { left: f32, right: f32 },
+    /// Only stops things falling onto its top edge
+    OneWay,
+}
+
+/// A custom property value on a tile
+#[derive(Clone, PartialEq, Debug)]
+pub enum TileProp {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    Str(String),
+}
+
+impl TileProp {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            TileProp::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+    /// Ints read as floats too, since editors don't always keep them apart
+    pub fn as_float(&self) -> Option<f32> {
+        match self {
+            TileProp::Float(f) => Some(*f),
+            TileProp::Int(i) => Some(*i as f32),
+            _ => None,
+        }
+    }
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            TileProp::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            TileProp::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// A graphical tile and what it does in the game
+#[derive(Clone, PartialEq, Debug)]
 pub struct Tile {
-    pub solid: bool, // ... any extra data like collision flags or other properties
+    pub shape: TileShape,
+    /// Scales how quickly things sliding on the tile slow down; 1.0 is normal, ice is lower
+    pub friction: f32,
+    /// Hurts whatever touches it, 0 for harmless tiles
+    pub damage: i32,
+    /// What the game calls this tile, e.g. "hoop" or "spikes"; empty if it's just scenery
+    pub kind: String,
+    /// Anything else the map editor attached to the tile
+    pub props: HashMap<String, TileProp>,
+}
+
+impl Default for Tile {
+    fn default() -> Self {
+        Self {
+            shape: TileShape::None,
+            friction: 1.0,
+            damage: 0,
+            kind: String::new(),
+            props: HashMap::new(),
+        }
+    }
+}
+
+impl Tile {
+    /// A tile nothing collides with
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// A tile that blocks from every side
+    pub fn solid() -> Self {
+        Self {
+            shape: TileShape::Full,
+            ..Self::default()
+        }
+    }
+
+    /// Blocks movement from every side, even if only part of the tile is filled;
+    /// one-way platforms don't count
+    pub fn is_solid(&self) -> bool {
+        matches!(
+            self.shape,
+            TileShape::Full | TileShape::Half | TileShape::Slope { .. }
+        )
+    }
+
+    pub fn is_kind(&self, kind: &str) -> bool {
+        self.kind == kind
+    }
+
+    pub fn prop(&self, name: &str) -> Option<&TileProp> {
+        self.props.get(name)
+    }
 }
 
 /// A set of tiles used in multiple Tilemaps
 pub struct Tileset {
     pub tiles: Vec<Tile>,
     image: Rc<Image>,
+    /// Every tile in the set is this big, and so is every cell of maps using it
+    tile_sz: Vec2i,
+    /// Pixels around the edge of the sheet
+    margin: i32,
+    /// Pixels between neighbouring tiles in the sheet
+    spacing: i32,
+    /// What an empty cell behaves like
+    empty: Tile,
+    /// Tiles that are drawn as a cycle of other tiles
+    anims: HashMap<TileID, TileAnim>,
+    /// Ticks so far, shared by every map using the set so their animated tiles stay in step
+    time: Cell<usize>,
+}
+
+/// A sequence of tiles to show in place of an animated tile, like water or torches
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TileAnim {
+    pub frames: Vec<TileID>,
+    /// How many ticks each frame stays up
+    pub durations: Vec<usize>,
+}
+
+impl TileAnim {
+    pub fn new(frames: Vec<(TileID, usize)>) -> Self {
+        assert!(
+            !frames.is_empty(),
+            "a tile animation needs at least one frame"
+        );
+        let (frames, durations) = frames.into_iter().map(|(id, d)| (id, d.max(1))).unzip();
+        Self { frames, durations }
+    }
+
+    /// Ticks before the animation starts over
+    pub fn period(&self) -> usize {
+        self.durations.iter().sum()
+    }
+
+    /// The frame showing `time` ticks in; it loops forever
+    pub fn frame_at(&self, time: usize) -> TileID {
+        let mut t = time % self.period();
+        for (id, d) in self.frames.iter().zip(self.durations.iter()) {
+            if t < *d {
+                return *id;
+            }
+            t -= d;
+        }
+        unreachable!()
+    }
 }
 
 /// Indices into a Tileset
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct TileID(usize);
 
+impl TileID {
+    /// A cell with nothing in it; it isn't drawn and isn't solid
+    pub const EMPTY: TileID = TileID(usize::MAX);
+
+    /// The tile at `idx` in a tileset
+    pub const fn new(idx: usize) -> Self {
+        TileID(idx)
+    }
+
+    /// Index into the tileset, or None for an empty cell
+    pub fn index(&self) -> Option<usize> {
+        if *self == TileID::EMPTY {
+            None
+        } else {
+            Some(self.0)
+        }
+    }
+}
+
 /// Grab a tile with a given ID
 impl std::ops::Index<TileID> for Tileset {
     type Output = Tile;
     fn index(&self, id: TileID) -> &Self::Output {
+        if id == TileID::EMPTY {
+            return &self.empty;
+        }
         &self.tiles[id.0]
     }
 }
 impl Tileset {
-    /// Create a new tileset
-    pub fn new(tiles: Vec<Tile>, texture: Rc<Image>) -> Self {
+    /// Create a new tileset of `tile_sz` tiles packed edge to edge in `texture`
+    pub fn new(tiles: Vec<Tile>, texture: Rc<Image>, tile_sz: Vec2i) -> Self {
+        assert!(tile_sz.x > 0 && tile_sz.y > 0, "tiles need a size");
         Self {
             tiles,
             image: texture,
+            tile_sz,
+            margin: 0,
+            spacing: 0,
+            empty: Tile::empty(),
+            anims: HashMap::new(),
+            time: Cell::new(0),
         }
     }
+
+    /// For sheets with a border of `margin` pixels and `spacing` pixels between tiles
+    pub fn with_spacing(mut self, margin: i32, spacing: i32) -> Self {
+        self.margin = margin;
+        self.spacing = spacing;
+        self
+    }
+
+    pub fn tile_sz(&self) -> Vec2i {
+        self.tile_sz
+    }
+
+    /// How many tiles fit across the sheet
+    pub fn columns(&self) -> usize {
+        let w = self.image.sz.x - 2 * self.margin + self.spacing;
+        (w / (self.tile_sz.x + self.spacing)).max(1) as usize
+    }
+
+    /// Make tile `id` cycle through other tiles when drawn.
+    /// Its own properties still decide how it collides.
+    pub fn set_animation(&mut self, id: TileID, anim: TileAnim) {
+        self.anims.insert(id, anim);
+    }
+
+    pub fn animation(&self, id: TileID) -> Option<&TileAnim> {
+        self.anims.get(&id)
+    }
+
+    pub fn is_animated(&self) -> bool {
+        !self.anims.is_empty()
+    }
+
+    /// Advance animated tiles by one frame, in every map using this set
+    pub fn tick(&self) {
+        self.time.set(self.time.get() + 1);
+    }
+
+    pub fn time(&self) -> usize {
+        self.time.get()
+    }
+
+    /// Which tile to draw for `id` at tick `time`
+    pub fn frame(&self, id: TileID, time: usize) -> TileID {
+        self.anims.get(&id).map_or(id, |anim| anim.frame_at(time))
+    }
+
     /// Get the frame rect for a tile ID
     fn get_rect(&self, id: TileID) -> Rect {
         let idx = id.0;
-        let (w, _h) = self.image.size();
-        let tw = w as usize / TILE_SZ;
+        let tw = self.columns();
         let row = idx / tw;
         let col = idx - (row * tw);
         Rect {
             pos: Vec2i {
-                x: col as i32 * TILE_SZ as i32,
-                y: row as i32 * TILE_SZ as i32,
-            },
-            sz: Vec2i {
-                x: TILE_SZ as i32,
-                y: TILE_SZ as i32,
+                x: self.margin + col as i32 * (self.tile_sz.x + self.spacing),
+                y: self.margin + row as i32 * (self.tile_sz.y + self.spacing),
             },
+            sz: self.tile_sz,
+        }
+    }
+}
+
+/// One grid of tiles in a Tilemap; layers are drawn in order, back to front
+pub struct TileLayer {
+    pub name: String,
+    /// How fast the layer scrolls relative to the view: 1.0 moves with the world,
+    /// smaller values hang back like distant scenery
+    pub parallax: (f32, f32),
+    /// 0.0 is invisible, 1.0 fully opaque
+    pub opacity: f32,
+    pub visible: bool,
+    /// Foreground layers are drawn after sprites, so things can walk behind them
+    pub foreground: bool,
+    /// A row-major grid of tile IDs in tileset
+    map: Vec<TileID>,
+}
+
+impl TileLayer {
+    pub fn new(name: &str, map: Vec<usize>) -> Self {
+        Self::from_ids(name, map.into_iter().map(TileID).collect())
+    }
+
+    fn from_ids(name: &str, map: Vec<TileID>) -> Self {
+        Self {
+            name: name.to_string(),
+            parallax: (1.0, 1.0),
+            opacity: 1.0,
+            visible: true,
+            foreground: false,
+            map,
         }
     }
 }
@@ -63,8 +328,26 @@ pub struct Tilemap {
     dims: (usize, usize),
     /// Which tileset is used for this tilemap
     tileset: Rc<Tileset>,
-    /// A row-major grid of tile IDs in tileset
-    map: Vec<TileID>,
+    /// Background to foreground
+    layers: Vec<TileLayer>,
+    /// Which layer tile queries (and so collision) look at
+    pub collision_layer: usize,
+    /// What tile queries report outside the map, e.g. a solid tile to wall the map in
+    pub out_of_bounds: Tile,
+    /// Cell rects edited since the last take_dirty
+    dirty: Vec<Rect>,
+    /// Pre-rendered chunks for each layer, built when first drawn and thrown out when edited
+    chunks: RefCell<Vec<Vec<Option<Rc<Chunk>>>>>,
+}
+
+/// A block of a layer's tiles rendered into one image, so it can be drawn in one go
+struct Chunk {
+    image: Rc<Image>,
+    /// Every pixel is solid, so it can be copied instead of blended
+    opaque: bool,
+    /// Animated tiles can't be baked in; these are drawn on top every frame,
+    /// as (offset in the chunk, index in the layer)
+    animated: Vec<(Vec2i, usize)>,
 }
 
 impl Tilemap {
@@ -75,47 +358,454 @@ impl Tilemap {
         map: Vec<usize>,
     ) -> Self {
         assert_eq!(dims.0 * dims.1, map.len(), "Tilemap is the wrong size!");
+        Self::from_layers(position, dims, tileset, vec![TileLayer::new("main", map)])
+    }
+
+    /// A single layer map with nothing in it yet
+    pub fn empty(position: Vec2i, dims: (usize, usize), tileset: Rc<Tileset>) -> Self {
+        let layer = TileLayer::from_ids("main", vec![TileID::EMPTY; dims.0 * dims.1]);
+        Self::from_layers(position, dims, tileset, vec![layer])
+    }
+
+    fn from_layers(
+        position: Vec2i,
+        dims: (usize, usize),
+        tileset: Rc<Tileset>,
+        layers: Vec<TileLayer>,
+    ) -> Self {
         Self {
             position,
             dims,
-            tileset: tileset,
-            map: map.into_iter().map(TileID).collect(),
+            tileset,
+            layers,
+            collision_layer: 0,
+            out_of_bounds: Tile::empty(),
+            dirty: vec![],
+            chunks: RefCell::new(vec![]),
         }
+        .with_fresh_chunks()
     }
 
-    pub fn tile_id_at(&self, Vec2i { x, y }: Vec2i) -> (Vec2i, TileID) {
-        // Translate into map coordinates
-        let x = (x - self.position.x) / TILE_SZ as i32;
-        let y = (y - self.position.y) / TILE_SZ as i32;
-        // return the tile corner and the tile ID
+    fn chunk_dims(&self) -> (usize, usize) {
         (
-            Vec2i {
-                x: x * TILE_SZ as i32 + self.position.x,
-                y: y * TILE_SZ as i32 + self.position.y,
-            },
-            self.map[y as usize * self.dims.0 + x as usize],
+            self.dims.0.div_ceil(CHUNK_TILES),
+            self.dims.1.div_ceil(CHUNK_TILES),
+        )
+    }
+
+    fn with_fresh_chunks(mut self) -> Self {
+        self.reset_chunks();
+        self
+    }
+
+    /// Throw out every cached chunk
+    fn reset_chunks(&mut self) {
+        let (cw, ch) = self.chunk_dims();
+        *self.chunks.get_mut() = vec![vec![None; cw * ch]; self.layers.len()];
+    }
+
+    /// Throw out the cached chunks of a layer covering a rect of cells
+    fn invalidate(&mut self, layer: usize, cells: Rect) {
+        let (cw, _) = self.chunk_dims();
+        let chunks = &mut self.chunks.get_mut()[layer];
+        let x0 = cells.pos.x as usize / CHUNK_TILES;
+        let y0 = cells.pos.y as usize / CHUNK_TILES;
+        let x1 = (cells.pos.x + cells.sz.x - 1) as usize / CHUNK_TILES;
+        let y1 = (cells.pos.y + cells.sz.y - 1) as usize / CHUNK_TILES;
+        for y in y0..=y1 {
+            for chunk in &mut chunks[y * cw + x0..=y * cw + x1] {
+                *chunk = None;
+            }
+        }
+    }
+
+    /// Render chunk (cx, cy) of a layer into its own image
+    fn bake_chunk(&self, layer: &TileLayer, (cx, cy): (usize, usize)) -> Chunk {
+        let x0 = cx * CHUNK_TILES;
+        let y0 = cy * CHUNK_TILES;
+        let x1 = (x0 + CHUNK_TILES).min(self.dims.0);
+        let y1 = (y0 + CHUNK_TILES).min(self.dims.1);
+        let tsz = self.tileset.tile_sz;
+        let mut image = Image::new(Vec2i {
+            x: (x1 - x0) as i32 * tsz.x,
+            y: (y1 - y0) as i32 * tsz.y,
+        });
+        image.clear((0, 0, 0, 0));
+        let mut animated = vec![];
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let idx = y * self.dims.0 + x;
+                let id = layer.map[idx];
+                let offset = Vec2i {
+                    x: (x - x0) as i32 * tsz.x,
+                    y: (y - y0) as i32 * tsz.y,
+                };
+                if id == TileID::EMPTY {
+                    continue;
+                }
+                if self.tileset.animation(id).is_some() {
+                    animated.push((offset, idx));
+                    continue;
+                }
+                image.bitblt(
+                    &self.tileset.image,
+                    self.tileset.get_rect(id),
+                    offset,
+                    false,
+                );
+            }
+        }
+        Chunk {
+            opaque: image.as_slice().iter().all(|px| px.3 == 255),
+            image: Rc::new(image),
+            animated,
+        }
+    }
+
+    /// The cached chunk (cx, cy) of a layer, baking it first if need be
+    fn chunk(&self, idx: usize, (cx, cy): (usize, usize)) -> Rc<Chunk> {
+        let (cw, _) = self.chunk_dims();
+        let mut chunks = self.chunks.borrow_mut();
+        chunks[idx][cy * cw + cx]
+            .get_or_insert_with(|| Rc::new(self.bake_chunk(&self.layers[idx], (cx, cy))))
+            .clone()
+    }
+
+    /// Put a new layer in front of the existing ones
+    pub fn add_layer(&mut self, layer: TileLayer) {
+        assert_eq!(
+            self.dims.0 * self.dims.1,
+            layer.map.len(),
+            "Layer {} is the wrong size!",
+            layer.name
+        );
+        self.layers.push(layer);
+        let (cw, ch) = self.chunk_dims();
+        self.chunks.get_mut().push(vec![None; cw * ch]);
+    }
+
+    pub fn layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    pub fn layer_mut(&mut self, idx: usize) -> &mut TileLayer {
+        &mut self.layers[idx]
+    }
+
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|l| l.name == name)
+    }
+
+    /// Which cell a world position falls in; may be outside the map.
+    /// Rounds toward negative infinity, so positions just left of or above the map
+    /// land in column or row -1 instead of 0.
+    pub fn cell_at(&self, Vec2i { x, y }: Vec2i) -> (i32, i32) {
+        (
+            (x - self.position.x).div_euclid(self.tileset.tile_sz.x),
+            (y - self.position.y).div_euclid(self.tileset.tile_sz.y),
         )
     }
 
+    /// World position of a cell's top left corner
+    pub fn cell_corner(&self, (cx, cy): (i32, i32)) -> Vec2i {
+        Vec2i {
+            x: cx * self.tileset.tile_sz.x + self.position.x,
+            y: cy * self.tileset.tile_sz.y + self.position.y,
+        }
+    }
+
+    /// The tile ID in a cell of a layer, or None outside the map
+    pub fn tile_id_in(&self, layer: usize, (cx, cy): (i32, i32)) -> Option<TileID> {
+        if cx < 0 || cy < 0 || cx as usize >= self.dims.0 || cy as usize >= self.dims.1 {
+            return None;
+        }
+        Some(self.layers[layer].map[cy as usize * self.dims.0 + cx as usize])
+    }
+
+    /// The tile ID in a cell of the collision layer, or None outside the map
+    pub fn tile_id_in_cell(&self, cell: (i32, i32)) -> Option<TileID> {
+        self.tile_id_in(self.collision_layer, cell)
+    }
+
+    /// The tile corner and tile ID at a world position, or None outside the map
+    pub fn tile_id_at(&self, posn: Vec2i) -> Option<(Vec2i, TileID)> {
+        let cell = self.cell_at(posn);
+        self.tile_id_in_cell(cell)
+            .map(|id| (self.cell_corner(cell), id))
+    }
+
     pub fn size(&self) -> (usize, usize) {
         self.dims
     }
 
-    pub fn tile_at(&self, posn: Vec2i) -> (Vec2i, Tile) {
-        let (pos, tile_id) = self.tile_id_at(posn);
-        (pos, self.tileset[tile_id])
+    /// Change one cell of a layer; cells outside the map are ignored.
+    /// Returns whether anything changed.
+    pub fn set_tile_in(&mut self, layer: usize, (cx, cy): (i32, i32), id: TileID) -> bool {
+        if cx < 0 || cy < 0 || cx as usize >= self.dims.0 || cy as usize >= self.dims.1 {
+            return false;
+        }
+        let cell = &mut self.layers[layer].map[cy as usize * self.dims.0 + cx as usize];
+        if *cell == id {
+            return false;
+        }
+        *cell = id;
+        let cells = Rect {
+            pos: Vec2i { x: cx, y: cy },
+            sz: Vec2i { x: 1, y: 1 },
+        };
+        self.invalidate(layer, cells);
+        self.dirty.push(cells);
+        true
+    }
+
+    /// Change one cell of the collision layer
+    pub fn set_tile(&mut self, cell: (i32, i32), id: TileID) -> bool {
+        self.set_tile_in(self.collision_layer, cell, id)
     }
 
+    /// Set every cell in `cells` (a rect in cell units) of a layer, clipped to the map
+    pub fn fill_rect(&mut self, layer: usize, cells: Rect, id: TileID) {
+        let x0 = cells.pos.x.max(0);
+        let y0 = cells.pos.y.max(0);
+        let x1 = (cells.pos.x + cells.sz.x).min(self.dims.0 as i32);
+        let y1 = (cells.pos.y + cells.sz.y).min(self.dims.1 as i32);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        let w = self.dims.0;
+        let map = &mut self.layers[layer].map;
+        for y in y0..y1 {
+            map[y as usize * w + x0 as usize..y as usize * w + x1 as usize].fill(id);
+        }
+        let cells = Rect {
+            pos: Vec2i { x: x0, y: y0 },
+            sz: Vec2i {
+                x: x1 - x0,
+                y: y1 - y0,
+            },
+        };
+        self.invalidate(layer, cells);
+        self.dirty.push(cells);
+    }
+
+    /// Change the map's size in cells, keeping what's in the top left and filling new cells with `fill`
+    pub fn resize(&mut self, dims: (usize, usize), fill: TileID) {
+        let old = self.dims;
+        for layer in self.layers.iter_mut() {
+            let mut map = vec![fill; dims.0 * dims.1];
+            for y in 0..old.1.min(dims.1) {
+                let n = old.0.min(dims.0);
+                map[y * dims.0..y * dims.0 + n]
+                    .copy_from_slice(&layer.map[y * old.0..y * old.0 + n]);
+            }
+            layer.map = map;
+        }
+        self.dims = dims;
+        self.reset_chunks();
+        self.dirty.clear();
+        self.dirty.push(Rect {
+            pos: Vec2i { x: 0, y: 0 },
+            sz: Vec2i {
+                x: dims.0 as i32,
+                y: dims.1 as i32,
+            },
+        });
+    }
+
+    /// Cell rects changed since the last call, so caches and game code can react to edits
+    pub fn take_dirty(&mut self) -> Vec<Rect> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    pub fn tileset(&self) -> &Rc<Tileset> {
+        &self.tileset
+    }
+
+    /// The tile in a cell of the collision layer; outside the map this is `out_of_bounds`
+    pub fn tile_in_cell(&self, cell: (i32, i32)) -> &Tile {
+        match self.tile_id_in_cell(cell) {
+            Some(id) => &self.tileset[id],
+            None => &self.out_of_bounds,
+        }
+    }
+
+    /// The tile corner and tile at a world position; outside the map this is `out_of_bounds`
+    pub fn tile_at(&self, posn: Vec2i) -> (Vec2i, &Tile) {
+        let cell = self.cell_at(posn);
+        (self.cell_corner(cell), self.tile_in_cell(cell))
+    }
+
+    /// Like tile_at, but None outside the map
+    pub fn get_tile(&self, posn: Vec2i) -> Option<(Vec2i, &Tile)> {
+        self.tile_id_at(posn)
+            .map(|(pos, id)| (pos, &self.tileset[id]))
+    }
+
+    /// Cells of the collision layer holding tiles of a given kind
+    pub fn cells_of_kind<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = (i32, i32)> + 'a {
+        let w = self.dims.0;
+        self.layers[self.collision_layer]
+            .map
+            .iter()
+            .enumerate()
+            .filter(move |(_, &id)| self.tileset[id].is_kind(kind))
+            .map(move |(i, _)| ((i % w) as i32, (i / w) as i32))
+    }
+
+    /// Where a layer's top left corner lands on screen when the view's top left is at `view`
+    fn layer_origin(&self, layer: &TileLayer, view: Vec2i) -> Vec2i {
+        Vec2i {
+            x: self.position.x - (view.x as f32 * layer.parallax.0) as i32,
+            y: self.position.y - (view.y as f32 * layer.parallax.1) as i32,
+        }
+    }
+
+    /// Visit each chunk of a layer that shows up on a screen of size `screen`,
+    /// with its screen position
+    fn for_each_chunk(
+        &self,
+        idx: usize,
+        view: Vec2i,
+        screen: Vec2i,
+        mut f: impl FnMut(Vec2i, &Chunk),
+    ) {
+        let origin = self.layer_origin(&self.layers[idx], view);
+        let csz = Vec2i {
+            x: CHUNK_TILES as i32 * self.tileset.tile_sz.x,
+            y: CHUNK_TILES as i32 * self.tileset.tile_sz.y,
+        };
+        let (cw, ch) = self.chunk_dims();
+        // Only the chunks that overlap the screen
+        let x0 = (-origin.x).div_euclid(csz.x).max(0) as usize;
+        let y0 = (-origin.y).div_euclid(csz.y).max(0) as usize;
+        let x1 = ((screen.x - origin.x + csz.x - 1).div_euclid(csz.x).max(0) as usize).min(cw);
+        let y1 = ((screen.y - origin.y + csz.y - 1).div_euclid(csz.y).max(0) as usize).min(ch);
+        for cy in y0..y1 {
+            for cx in x0..x1 {
+                let pos = Vec2i {
+                    x: cx as i32 * csz.x + origin.x,
+                    y: cy as i32 * csz.y + origin.y,
+                };
+                f(pos, &self.chunk(idx, (cx, cy)));
+            }
+        }
+    }
+
+    /// The current frame of each animated tile in a chunk drawn at `pos`
+    fn animated_frames<'a>(
+        &'a self,
+        layer: &'a TileLayer,
+        chunk: &'a Chunk,
+        pos: Vec2i,
+    ) -> impl Iterator<Item = (Vec2i, Rect)> + 'a {
+        chunk.animated.iter().map(move |(offset, idx)| {
+            let frame = self.tileset.frame(layer.map[*idx], self.tileset.time());
+            (
+                Vec2i {
+                    x: pos.x + offset.x,
+                    y: pos.y + offset.y,
+                },
+                self.tileset.get_rect(frame),
+            )
+        })
+    }
+
+    pub fn draw_layer(&self, idx: usize, screen: &mut Image, view: Vec2i) {
+        let layer = &self.layers[idx];
+        if !layer.visible {
+            return;
+        }
+        let alpha = (layer.opacity.clamp(0.0, 1.0) * 255.0) as u8;
+        let sz = screen.sz;
+        self.for_each_chunk(idx, view, sz, |pos, chunk| {
+            let whole = Rect {
+                pos: Vec2i { x: 0, y: 0 },
+                sz: chunk.image.sz,
+            };
+            if chunk.opaque && alpha == 255 {
+                screen.copy_rect(&chunk.image, whole, pos);
+            } else {
+                screen.bitblt_alpha(&chunk.image, whole, pos, false, alpha);
+            }
+            for (pos, frame) in self.animated_frames(layer, chunk, pos) {
+                screen.bitblt_alpha(&self.tileset.image, frame, pos, false, alpha);
+            }
+        });
+    }
+
+    /// Draw every layer, back to front
     pub fn draw(&self, screen: &mut Image) {
-        for (y, row) in self.map.chunks_exact(self.dims.0).enumerate() {
-            // We are in tile coordinates at this point so we'll need to translate back to pixel units and world coordinates to draw.
-            let ypx = (y * TILE_SZ) as i32 + self.position.y;
-            // Here we can iterate through the column index and tiles in the row in parallel
-            for (x, id) in row.iter().enumerate() {
-                let xpx = (x * TILE_SZ) as i32 + self.position.x;
-                let frame = self.tileset.get_rect(*id);
-                screen.bitblt(&self.tileset.image, frame, Vec2i { x: xpx, y: ypx }, false);
+        for idx in 0..self.layers.len() {
+            self.draw_layer(idx, screen, Vec2i { x: 0, y: 0 });
+        }
+    }
+
+    /// The area the map covers in the world
+    pub fn world_rect(&self) -> Rect {
+        Rect {
+            pos: self.position,
+            sz: Vec2i {
+                x: self.dims.0 as i32 * self.tileset.tile_sz.x,
+                y: self.dims.1 as i32 * self.tileset.tile_sz.y,
+            },
+        }
+    }
+
+    /// Queue the tiles the camera can see instead of drawing right away.
+    /// Background layers go under the sprites and foreground layers over them.
+    pub fn queue_draw(&self, list: &mut DrawList, camera: &Camera) {
+        for (idx, layer) in self.layers.iter().enumerate() {
+            if !layer.visible {
+                continue;
             }
+            let base = if layer.foreground {
+                draw::FOREGROUND
+            } else {
+                draw::BACKGROUND
+            };
+            let alpha = (layer.opacity.clamp(0.0, 1.0) * 255.0) as u8;
+            self.for_each_chunk(idx, camera.view(), camera.size, |pos, chunk| {
+                let whole = Rect {
+                    pos: Vec2i { x: 0, y: 0 },
+                    sz: chunk.image.sz,
+                };
+                // Parallax means these are already screen positions
+                list.push(DrawCmd {
+                    layer: base + idx as i32,
+                    z: 0,
+                    bounds: Rect { pos, sz: whole.sz },
+                    kind: if chunk.opaque && alpha == 255 {
+                        DrawKind::Copy {
+                            image: chunk.image.clone(),
+                            from: whole,
+                        }
+                    } else {
+                        DrawKind::Blit {
+                            image: chunk.image.clone(),
+                            from: whole,
+                            flip: false,
+                            alpha,
+                        }
+                    },
+                });
+                for (pos, frame) in self.animated_frames(layer, chunk, pos) {
+                    list.push(DrawCmd {
+                        layer: base + idx as i32,
+                        z: 0,
+                        bounds: Rect { pos, sz: frame.sz },
+                        kind: DrawKind::Blit {
+                            image: self.tileset.image.clone(),
+                            from: frame,
+                            flip: false,
+                            alpha,
+                        },
+                    });
+                }
+            });
         }
     }
 }
@@ -0,0 +1,247 @@
+//! Picking edge and corner tiles from a grid of terrain instead of placing them by hand.
+//!
+//! Each cell gets a bitmask of which neighbours share its terrain, and a rule set turns the
+//! bitmask into a tile. Two common layouts are built in: 16-tile sets that only look at the
+//! four edges, and 47-tile "blob" sets that also look at corners.
+
+use super::{TileID, Tilemap};
+
+use std::collections::HashMap;
+
+/// Terrain 0 is no terrain; the autotiler leaves those cells alone
+pub type Terrain = u8;
+pub const NO_TERRAIN: Terrain = 0;
+
+// Neighbour bits, clockwise from the top
+pub const N: u8 = 1;
+pub const NE: u8 = 2;
+pub const E: u8 = 4;
+pub const SE: u8 = 8;
+pub const S: u8 = 16;
+pub const SW: u8 = 32;
+pub const W: u8 = 64;
+pub const NW: u8 = 128;
+
+const NEIGHBOURS: [(i32, i32, u8); 8] = [
+    (0, -1, N),
+    (1, -1, NE),
+    (1, 0, E),
+    (1, 1, SE),
+    (0, 1, S),
+    (-1, 1, SW),
+    (-1, 0, W),
+    (-1, -1, NW),
+];
+
+/// Which neighbours a rule set cares about
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bitmask {
+    /// Just N, E, S and W: 16 combinations
+    Edges,
+    /// Edges plus corners, where a corner only counts if both edges next to it match:
+    /// 47 combinations
+    Blob,
+}
+
+impl Bitmask {
+    /// Drop the bits this kind of bitmask ignores
+    pub fn reduce(&self, mask: u8) -> u8 {
+        let edges = mask & (N | E | S | W);
+        match self {
+            Bitmask::Edges => edges,
+            Bitmask::Blob => {
+                let mut out = edges;
+                for (corner, a, b) in [(NE, N, E), (SE, S, E), (SW, S, W), (NW, N, W)] {
+                    if mask & corner != 0 && edges & a != 0 && edges & b != 0 {
+                        out |= corner;
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    /// Every distinct reduced mask, smallest first; this is the order tiles are laid out in
+    pub fn masks(&self) -> Vec<u8> {
+        (0..=255).filter(|m| self.reduce(*m) == *m).collect()
+    }
+}
+
+/// Maps a terrain's neighbour bitmasks to tiles
+#[derive(Clone, Debug)]
+pub struct AutotileRules {
+    pub bitmask: Bitmask,
+    tiles: HashMap<u8, TileID>,
+    /// Used for any mask without a tile of its own
+    pub fallback: TileID,
+}
+
+impl AutotileRules {
+    pub fn new(bitmask: Bitmask, fallback: TileID) -> Self {
+        Self {
+            bitmask,
+            tiles: HashMap::new(),
+            fallback,
+        }
+    }
+
+    /// A 16-tile set laid out in the tileset in mask order, starting at `first`.
+    /// The edge bits count up as N=1, E=2, S=4, W=8.
+    pub fn sixteen(first: TileID) -> Self {
+        let first = first
+            .index()
+            .expect("autotile set can't start at an empty tile");
+        let mut rules = Self::new(Bitmask::Edges, TileID::new(first + 15));
+        for mask in Bitmask::Edges.masks() {
+            let wang = (mask & N != 0) as usize
+                | ((mask & E != 0) as usize) << 1
+                | ((mask & S != 0) as usize) << 2
+                | ((mask & W != 0) as usize) << 3;
+            rules.set(mask, TileID::new(first + wang));
+        }
+        rules
+    }
+
+    /// A 47-tile blob set laid out in the tileset in ascending mask order, starting at `first`
+    pub fn blob(first: TileID) -> Self {
+        let first = first
+            .index()
+            .expect("autotile set can't start at an empty tile");
+        let masks = Bitmask::Blob.masks();
+        let mut rules = Self::new(Bitmask::Blob, TileID::new(first + masks.len() - 1));
+        for (i, mask) in masks.into_iter().enumerate() {
+            rules.set(mask, TileID::new(first + i));
+        }
+        rules
+    }
+
+    /// Use `id` for cells whose neighbours match `mask` (it gets reduced first)
+    pub fn set(&mut self, mask: u8, id: TileID) {
+        self.tiles.insert(self.bitmask.reduce(mask), id);
+    }
+
+    pub fn pick(&self, mask: u8) -> TileID {
+        *self
+            .tiles
+            .get(&self.bitmask.reduce(mask))
+            .unwrap_or(&self.fallback)
+    }
+}
+
+/// A terrain grid over one layer of a Tilemap, which it keeps tiled to match
+pub struct Autotiler {
+    pub layer: usize,
+    dims: (usize, usize),
+    terrain: Vec<Terrain>,
+    rules: HashMap<Terrain, AutotileRules>,
+    /// Whether cells past the edge of the map count as matching, so terrain runs off-screen
+    /// instead of growing a border
+    pub edges_match: bool,
+}
+
+impl Autotiler {
+    /// An empty terrain grid the size of `map`
+    pub fn new(map: &Tilemap, layer: usize) -> Self {
+        let dims = map.size();
+        Self {
+            layer,
+            dims,
+            terrain: vec![NO_TERRAIN; dims.0 * dims.1],
+            rules: HashMap::new(),
+            edges_match: true,
+        }
+    }
+
+    pub fn add_rules(&mut self, terrain: Terrain, rules: AutotileRules) {
+        assert_ne!(terrain, NO_TERRAIN, "terrain 0 means no terrain");
+        self.rules.insert(terrain, rules);
+    }
+
+    fn index(&self, (cx, cy): (i32, i32)) -> Option<usize> {
+        if cx < 0 || cy < 0 || cx as usize >= self.dims.0 || cy as usize >= self.dims.1 {
+            return None;
+        }
+        Some(cy as usize * self.dims.0 + cx as usize)
+    }
+
+    pub fn terrain(&self, cell: (i32, i32)) -> Option<Terrain> {
+        self.index(cell).map(|i| self.terrain[i])
+    }
+
+    /// Which neighbours of a cell share its terrain
+    pub fn mask(&self, (cx, cy): (i32, i32)) -> u8 {
+        let here = match self.terrain((cx, cy)) {
+            Some(t) => t,
+            None => return 0,
+        };
+        let mut mask = 0;
+        for (dx, dy, bit) in NEIGHBOURS {
+            let matches = match self.terrain((cx + dx, cy + dy)) {
+                Some(t) => t == here,
+                None => self.edges_match,
+            };
+            if matches {
+                mask |= bit;
+            }
+        }
+        mask
+    }
+
+    /// Put the right tile in one cell of the map; cells with no terrain are left as they are
+    fn retile(&self, map: &mut Tilemap, cell: (i32, i32)) {
+        let terrain = match self.terrain(cell) {
+            Some(t) if t != NO_TERRAIN => t,
+            _ => return,
+        };
+        let rules = self
+            .rules
+            .get(&terrain)
+            .unwrap_or_else(|| panic!("no autotile rules for terrain {}", terrain));
+        map.set_tile_in(self.layer, cell, rules.pick(self.mask(cell)));
+    }
+
+    /// Retile the whole map from the terrain grid
+    pub fn apply(&self, map: &mut Tilemap) {
+        assert_eq!(
+            map.size(),
+            self.dims,
+            "autotiler and map are different sizes"
+        );
+        for y in 0..self.dims.1 as i32 {
+            for x in 0..self.dims.0 as i32 {
+                self.retile(map, (x, y));
+            }
+        }
+    }
+
+    /// Change a cell's terrain and retile it and its neighbours.
+    /// Clearing a cell's terrain empties its tile.
+    pub fn set_terrain(&mut self, map: &mut Tilemap, (cx, cy): (i32, i32), terrain: Terrain) {
+        let idx = match self.index((cx, cy)) {
+            Some(i) => i,
+            None => return,
+        };
+        if self.terrain[idx] == terrain {
+            return;
+        }
+        self.terrain[idx] = terrain;
+        if terrain == NO_TERRAIN {
+            map.set_tile_in(self.layer, (cx, cy), TileID::EMPTY);
+        }
+        self.retile(map, (cx, cy));
+        for (dx, dy, _) in NEIGHBOURS {
+            self.retile(map, (cx + dx, cy + dy));
+        }
+    }
+
+    /// Fill the terrain grid from a row-major list and retile everything
+    pub fn set_grid(&mut self, map: &mut Tilemap, grid: Vec<Terrain>) {
+        assert_eq!(
+            grid.len(),
+            self.terrain.len(),
+            "terrain grid is the wrong size"
+        );
+        self.terrain = grid;
+        self.apply(map);
+    }
+}
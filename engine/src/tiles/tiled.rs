@@ -0,0 +1,517 @@
+//! Loading tilesets and tilemaps saved by the Tiled map editor (https://www.mapeditor.org).
+//!
+//! Both the XML (`.tmx`/`.tsx`) and JSON (`.tmj`/`.tsj`) formats are understood,
+//! with CSV or base64 (optionally zlib/gzip compressed) layer data and external tilesets.
+//! Tile properties: `shape` is one of `full`, `half`, `slope` (with float `slope_left` and
+//! `slope_right` heights) or `oneway`, and a bool `solid` is shorthand for `full`. A float
+//! `friction`, int `damage` and string `kind` fill in the rest; the tile's type/class is its
+//! kind too. Any other property ends up in `Tile::props`.
+//! Tile animations are kept, with their millisecond durations turned into ticks.
+//! Layer properties: a bool `foreground` draws the layer over sprites, and a bool
+//! `collision` picks the layer tile queries use (otherwise the first layer).
+
+use super::{Tile, TileAnim, TileID, TileLayer, TileProp, TileShape, Tilemap, Tileset};
+use crate::types::{Image, Vec2i};
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// The top bits of a Tiled global tile ID are flip flags, which we don't draw
+const GID_FLAGS: u32 = 0xF000_0000;
+
+// Tiled times animation frames in milliseconds; the engine ticks at 60fps
+const TICKS_PER_SECOND: u64 = 60;
+
+/// A tileset as Tiled describes it, before its image is loaded
+struct RawTileset {
+    firstgid: u32,
+    image: PathBuf,
+    tile_sz: Vec2i,
+    margin: i32,
+    spacing: i32,
+    tiles: Vec<Tile>,
+    anims: Vec<(TileID, TileAnim)>,
+}
+
+struct RawLayer {
+    name: String,
+    width: usize,
+    height: usize,
+    parallax: (f32, f32),
+    opacity: f32,
+    visible: bool,
+    foreground: bool,
+    collision: bool,
+    gids: Vec<u32>,
+}
+
+struct RawMap {
+    tilesets: Vec<RawTileset>,
+    layers: Vec<RawLayer>,
+}
+
+fn is_json(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("tmj") | Some("tsj") | Some("json")
+    )
+}
+
+fn read(path: &Path) -> String {
+    std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("couldn't read {}: {}", path.display(), e))
+}
+
+fn dir_of(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new(""))
+}
+
+fn empty_tiles(count: usize) -> Vec<Tile> {
+    vec![Tile::empty(); count]
+}
+
+/// Apply one Tiled tile property, either to a built-in field or the tile's own props
+fn set_property(tile: &mut Tile, name: &str, value: TileProp) {
+    let slope = |tile: &Tile| match tile.shape {
+        TileShape::Slope { left, right } => (left, right),
+        _ => (0.0, 1.0),
+    };
+    match (name, &value) {
+        ("solid", TileProp::Bool(solid)) => {
+            tile.shape = if *solid {
+                TileShape::Full
+            } else {
+                TileShape::None
+            }
+        }
+        ("shape", TileProp::Str(shape)) => {
+            let (left, right) = slope(tile);
+            tile.shape = match shape.as_str() {
+                "none" | "" => TileShape::None,
+                "full" => TileShape::Full,
+                "half" => TileShape::Half,
+                "slope" => TileShape::Slope { left, right },
+                "oneway" | "one-way" => TileShape::OneWay,
+                other => panic!("unknown tile shape {:?}", other),
+            }
+        }
+        ("slope_left", _) | ("slope_right", _) => {
+            let (mut left, mut right) = slope(tile);
+            let height = value
+                .as_float()
+                .unwrap_or_else(|| panic!("{} should be a number", name));
+            if name == "slope_left" {
+                left = height;
+            } else {
+                right = height;
+            }
+            tile.shape = TileShape::Slope { left, right };
+        }
+        ("friction", _) => tile.friction = value.as_float().expect("friction should be a number"),
+        ("damage", _) => tile.damage = value.as_int().expect("damage should be an int") as i32,
+        ("kind", TileProp::Str(kind)) => tile.kind = kind.clone(),
+        _ => {
+            tile.props.insert(name.to_string(), value);
+        }
+    }
+}
+
+fn ms_to_ticks(ms: u64) -> usize {
+    (ms * TICKS_PER_SECOND / 1000).max(1) as usize
+}
+
+fn xml_property(p: roxmltree::Node) -> TileProp {
+    // Multi-line strings live in the element's text instead of `value`
+    let value = p.attribute("value").or_else(|| p.text()).unwrap_or("");
+    match p.attribute("type") {
+        Some("bool") => TileProp::Bool(value == "true"),
+        Some("int") | Some("object") => TileProp::Int(
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("bad int property {:?}", value)),
+        ),
+        Some("float") => TileProp::Float(
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("bad float property {:?}", value)),
+        ),
+        _ => TileProp::Str(value.to_string()),
+    }
+}
+
+fn json_property(p: &serde_json::Value) -> TileProp {
+    let value = &p["value"];
+    match p["type"].as_str() {
+        Some("bool") => TileProp::Bool(value.as_bool().unwrap_or(false)),
+        Some("int") | Some("object") => TileProp::Int(value.as_i64().unwrap_or(0)),
+        Some("float") => TileProp::Float(value.as_f64().unwrap_or(0.0) as f32),
+        _ => TileProp::Str(value.as_str().unwrap_or("").to_string()),
+    }
+}
+
+/// Turn layer data into global tile IDs, whatever way Tiled encoded it
+fn decode_gids(text: &str, encoding: Option<&str>, compression: Option<&str>) -> Vec<u32> {
+    match encoding {
+        Some("csv") => text
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse()
+                    .unwrap_or_else(|_| panic!("bad tile ID {:?} in layer", s))
+            })
+            .collect(),
+        Some("base64") => {
+            let bytes = base64::decode(text.trim()).expect("layer data isn't valid base64");
+            let mut raw = vec![];
+            match compression {
+                None | Some("") => raw = bytes,
+                Some("zlib") => {
+                    flate2::read::ZlibDecoder::new(&bytes[..])
+                        .read_to_end(&mut raw)
+                        .expect("bad zlib layer data");
+                }
+                Some("gzip") => {
+                    flate2::read::GzDecoder::new(&bytes[..])
+                        .read_to_end(&mut raw)
+                        .expect("bad gzip layer data");
+                }
+                Some(other) => panic!("unsupported layer compression {:?}", other),
+            }
+            raw.chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        }
+        other => panic!("unsupported layer encoding {:?}", other),
+    }
+}
+
+fn parse_tsx(node: roxmltree::Node, firstgid: u32, dir: &Path) -> RawTileset {
+    let attr = |name: &str| -> u32 {
+        node.attribute(name)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(|| panic!("tileset is missing {}", name))
+    };
+    let image = node
+        .children()
+        .find(|n| n.has_tag_name("image"))
+        .and_then(|n| n.attribute("source"))
+        .expect("only single-image tilesets are supported");
+    let mut tiles = empty_tiles(attr("tilecount") as usize);
+    let mut anims = vec![];
+    for tile in node.children().filter(|n| n.has_tag_name("tile")) {
+        let id: usize = tile.attribute("id").and_then(|v| v.parse().ok()).unwrap();
+        if let Some(kind) = tile.attribute("type").or_else(|| tile.attribute("class")) {
+            tiles[id].kind = kind.to_string();
+        }
+        for p in tile
+            .children()
+            .filter(|n| n.has_tag_name("properties"))
+            .flat_map(|n| n.children())
+            .filter(|n| n.has_tag_name("property"))
+        {
+            set_property(
+                &mut tiles[id],
+                p.attribute("name").unwrap_or(""),
+                xml_property(p),
+            );
+        }
+        if let Some(anim) = tile.children().find(|n| n.has_tag_name("animation")) {
+            let frames = anim
+                .children()
+                .filter(|n| n.has_tag_name("frame"))
+                .map(|f| {
+                    let num = |name: &str| -> u64 {
+                        f.attribute(name)
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or_else(|| panic!("animation frame is missing {}", name))
+                    };
+                    (TileID(num("tileid") as usize), ms_to_ticks(num("duration")))
+                })
+                .collect();
+            anims.push((TileID(id), TileAnim::new(frames)));
+        }
+    }
+    RawTileset {
+        firstgid,
+        image: dir.join(image),
+        tile_sz: Vec2i {
+            x: attr("tilewidth") as i32,
+            y: attr("tileheight") as i32,
+        },
+        margin: node.attribute("margin").map_or(0, |v| v.parse().unwrap()),
+        spacing: node.attribute("spacing").map_or(0, |v| v.parse().unwrap()),
+        tiles,
+        anims,
+    }
+}
+
+fn parse_tsj(json: &serde_json::Value, firstgid: u32, dir: &Path) -> RawTileset {
+    let num = |name: &str| -> u64 {
+        json[name]
+            .as_u64()
+            .unwrap_or_else(|| panic!("tileset is missing {}", name))
+    };
+    let image = json["image"]
+        .as_str()
+        .expect("only single-image tilesets are supported");
+    let mut tiles = empty_tiles(num("tilecount") as usize);
+    let mut anims = vec![];
+    for tile in json["tiles"].as_array().into_iter().flatten() {
+        let id = tile["id"].as_u64().unwrap() as usize;
+        if let Some(kind) = tile["type"].as_str().or_else(|| tile["class"].as_str()) {
+            tiles[id].kind = kind.to_string();
+        }
+        for p in tile["properties"].as_array().into_iter().flatten() {
+            set_property(
+                &mut tiles[id],
+                p["name"].as_str().unwrap_or(""),
+                json_property(p),
+            );
+        }
+        if let Some(frames) = tile["animation"].as_array() {
+            let frames = frames
+                .iter()
+                .map(|f| {
+                    (
+                        TileID(f["tileid"].as_u64().unwrap() as usize),
+                        ms_to_ticks(f["duration"].as_u64().unwrap()),
+                    )
+                })
+                .collect();
+            anims.push((TileID(id), TileAnim::new(frames)));
+        }
+    }
+    RawTileset {
+        firstgid,
+        image: dir.join(image),
+        tile_sz: Vec2i {
+            x: num("tilewidth") as i32,
+            y: num("tileheight") as i32,
+        },
+        margin: json["margin"].as_i64().unwrap_or(0) as i32,
+        spacing: json["spacing"].as_i64().unwrap_or(0) as i32,
+        tiles,
+        anims,
+    }
+}
+
+fn load_external_tileset(path: &Path, firstgid: u32) -> RawTileset {
+    let text = read(path);
+    if is_json(path) {
+        let json: serde_json::Value = serde_json::from_str(&text)
+            .unwrap_or_else(|e| panic!("{} isn't valid JSON: {}", path.display(), e));
+        parse_tsj(&json, firstgid, dir_of(path))
+    } else {
+        let doc = roxmltree::Document::parse(&text)
+            .unwrap_or_else(|e| panic!("{} isn't valid XML: {}", path.display(), e));
+        parse_tsx(doc.root_element(), firstgid, dir_of(path))
+    }
+}
+
+fn parse_tmx(text: &str, dir: &Path) -> RawMap {
+    let doc = roxmltree::Document::parse(text).unwrap_or_else(|e| panic!("bad map XML: {}", e));
+    let map = doc.root_element();
+    let mut tilesets = vec![];
+    let mut layers = vec![];
+    for node in map.children() {
+        if node.has_tag_name("tileset") {
+            let firstgid = node.attribute("firstgid").unwrap().parse().unwrap();
+            tilesets.push(match node.attribute("source") {
+                Some(source) => load_external_tileset(&dir.join(source), firstgid),
+                None => parse_tsx(node, firstgid, dir),
+            });
+        } else if node.has_tag_name("layer") {
+            let dim = |name: &str| -> usize { node.attribute(name).unwrap().parse().unwrap() };
+            let float =
+                |name: &str| -> f32 { node.attribute(name).map_or(1.0, |v| v.parse().unwrap()) };
+            let flag = |name: &str| -> bool {
+                node.children()
+                    .filter(|n| n.has_tag_name("properties"))
+                    .flat_map(|n| n.children())
+                    .any(|p| {
+                        p.attribute("name") == Some(name) && p.attribute("value") == Some("true")
+                    })
+            };
+            let data = node
+                .children()
+                .find(|n| n.has_tag_name("data"))
+                .expect("layer has no data");
+            let gids = match data.attribute("encoding") {
+                None => data
+                    .children()
+                    .filter(|n| n.has_tag_name("tile"))
+                    .map(|t| t.attribute("gid").map_or(0, |g| g.parse().unwrap()))
+                    .collect(),
+                encoding => decode_gids(
+                    data.text().unwrap_or(""),
+                    encoding,
+                    data.attribute("compression"),
+                ),
+            };
+            layers.push(RawLayer {
+                name: node.attribute("name").unwrap_or("").to_string(),
+                width: dim("width"),
+                height: dim("height"),
+                parallax: (float("parallaxx"), float("parallaxy")),
+                opacity: float("opacity"),
+                visible: node.attribute("visible") != Some("0"),
+                foreground: flag("foreground"),
+                collision: flag("collision"),
+                gids,
+            });
+        }
+    }
+    RawMap { tilesets, layers }
+}
+
+fn parse_tmj(text: &str, dir: &Path) -> RawMap {
+    let map: serde_json::Value =
+        serde_json::from_str(text).unwrap_or_else(|e| panic!("bad map JSON: {}", e));
+    let tilesets = map["tilesets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|ts| {
+            let firstgid = ts["firstgid"].as_u64().unwrap() as u32;
+            match ts["source"].as_str() {
+                Some(source) => load_external_tileset(&dir.join(source), firstgid),
+                None => parse_tsj(ts, firstgid, dir),
+            }
+        })
+        .collect();
+    let layers = map["layers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|l| l["type"] == "tilelayer")
+        .map(|l| {
+            let gids = match &l["data"] {
+                serde_json::Value::Array(ids) => {
+                    ids.iter().map(|id| id.as_u64().unwrap() as u32).collect()
+                }
+                serde_json::Value::String(text) => {
+                    decode_gids(text, l["encoding"].as_str(), l["compression"].as_str())
+                }
+                _ => panic!("layer has no data"),
+            };
+            let float = |name: &str| l[name].as_f64().unwrap_or(1.0) as f32;
+            let flag = |name: &str| {
+                l["properties"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .any(|p| p["name"] == name && p["value"] == true)
+            };
+            RawLayer {
+                name: l["name"].as_str().unwrap_or("").to_string(),
+                width: l["width"].as_u64().unwrap() as usize,
+                height: l["height"].as_u64().unwrap() as usize,
+                parallax: (float("parallaxx"), float("parallaxy")),
+                opacity: float("opacity"),
+                visible: l["visible"].as_bool().unwrap_or(true),
+                foreground: flag("foreground"),
+                collision: flag("collision"),
+                gids,
+            }
+        })
+        .collect();
+    RawMap { tilesets, layers }
+}
+
+impl RawTileset {
+    fn build(self) -> Tileset {
+        let mut tileset = Tileset::new(
+            self.tiles,
+            Rc::new(Image::from_file(&self.image)),
+            self.tile_sz,
+        )
+        .with_spacing(self.margin, self.spacing);
+        for (id, anim) in self.anims {
+            tileset.set_animation(id, anim);
+        }
+        tileset
+    }
+}
+
+impl Tileset {
+    /// Load a standalone Tiled tileset (`.tsx` or `.tsj`)
+    pub fn from_tiled(path: &Path) -> Self {
+        load_external_tileset(path, 1).build()
+    }
+}
+
+impl Tilemap {
+    /// Load every tile layer of a Tiled map (`.tmx` or `.tmj`), positioned at the origin.
+    /// The map must use a single tileset; empty cells become `TileID::EMPTY`.
+    pub fn from_tiled(path: &Path) -> Self {
+        let text = read(path);
+        let raw = if is_json(path) {
+            parse_tmj(&text, dir_of(path))
+        } else {
+            parse_tmx(&text, dir_of(path))
+        };
+        let mut tilesets = raw.tilesets.into_iter();
+        let tileset = tilesets
+            .next()
+            .unwrap_or_else(|| panic!("{} has no tileset", path.display()));
+        assert!(
+            tilesets.next().is_none(),
+            "{} uses more than one tileset",
+            path.display()
+        );
+        let first = raw
+            .layers
+            .first()
+            .unwrap_or_else(|| panic!("{} has no tile layers", path.display()));
+        let dims = (first.width, first.height);
+        let collision_layer = raw.layers.iter().position(|l| l.collision).unwrap_or(0);
+        let firstgid = tileset.firstgid;
+        let tile_count = tileset.tiles.len();
+        let layers = raw
+            .layers
+            .into_iter()
+            .map(|layer| {
+                assert_eq!(
+                    (layer.width, layer.height),
+                    dims,
+                    "layer {} is a different size from the map",
+                    layer.name
+                );
+                assert_eq!(
+                    layer.width * layer.height,
+                    layer.gids.len(),
+                    "layer {} data is the wrong size",
+                    layer.name
+                );
+                let map = layer
+                    .gids
+                    .into_iter()
+                    .map(|gid| match gid & !GID_FLAGS {
+                        0 => TileID::EMPTY,
+                        gid => {
+                            let idx = gid
+                                .checked_sub(firstgid)
+                                .map(|idx| idx as usize)
+                                .filter(|&idx| idx < tile_count)
+                                .unwrap_or_else(|| panic!("tile ID {} is not in the tileset", gid));
+                            TileID(idx)
+                        }
+                    })
+                    .collect();
+                TileLayer {
+                    parallax: layer.parallax,
+                    opacity: layer.opacity,
+                    visible: layer.visible,
+                    foreground: layer.foreground,
+                    ..TileLayer::from_ids(&layer.name, map)
+                }
+            })
+            .collect();
+        let mut map =
+            Self::from_layers(Vec2i { x: 0, y: 0 }, dims, Rc::new(tileset.build()), layers);
+        map.collision_layer = collision_layer;
+        map
+    }
+}
@@ -1,6 +1,6 @@
 pub type Color = (u8, u8, u8, u8);
 
-#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Default)]
 pub struct Vec2i {
     pub x: i32,
     pub y: i32,
@@ -17,7 +17,7 @@ impl std::ops::Add<Vec2i> for Vec2i {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Rect {
     pub pos: Vec2i,
     pub sz: Vec2i,
@@ -37,6 +37,14 @@ impl Rect {
         self.pos.y + self.sz.y >= point.y
     }
 
+    /// Whether the two rects share any area; touching edges don't count
+    pub fn overlaps(&self, other: Rect) -> bool {
+        self.pos.x < other.pos.x + other.sz.x
+            && other.pos.x < self.pos.x + self.sz.x
+            && self.pos.y < other.pos.y + other.sz.y
+            && other.pos.y < self.pos.y + self.sz.y
+    }
+
     pub fn move_by(&mut self, dx: i32, dy: i32) {
         self.pos.x += dx;
         self.pos.y += dy;
@@ -139,6 +147,37 @@ impl Image {
     }
 
     pub fn bitblt(&mut self, src: &Image, from: Rect, to: Vec2i, flip: bool) {
+        self.bitblt_alpha(src, from, to, flip, 255);
+    }
+
+    /// Like bitblt, but copies pixels over without blending; only right for opaque sources
+    pub fn copy_rect(&mut self, src: &Image, from: Rect, to: Vec2i) {
+        assert!(Rect {
+            pos: Vec2i { x: 0, y: 0 },
+            sz: src.sz
+        }
+        .contains(from));
+        let x_skip = (-to.x).max(0);
+        let y_skip = (-to.y).max(0);
+        let x_count = (to.x + from.sz.x).min(self.sz.x) - to.x - x_skip;
+        let y_count = (to.y + from.sz.y).min(self.sz.y) - to.y - y_skip;
+        if x_count <= 0 || y_count <= 0 {
+            return;
+        }
+        let src_pitch = src.sz.x as usize;
+        let dst_pitch = self.sz.x as usize;
+        for row in 0..y_count as usize {
+            let from_start =
+                ((from.pos.y + y_skip) as usize + row) * src_pitch + (from.pos.x + x_skip) as usize;
+            let to_start = ((to.y + y_skip) as usize + row) * dst_pitch + (to.x + x_skip) as usize;
+            self.buffer[to_start..to_start + x_count as usize]
+                .copy_from_slice(&src.buffer[from_start..from_start + x_count as usize]);
+        }
+    }
+
+    /// Like bitblt, but with the source faded by `alpha` (255 is fully opaque)
+    pub fn bitblt_alpha(&mut self, src: &Image, from: Rect, to: Vec2i, flip: bool, alpha: u8) {
+        let opacity = alpha as f32 / 255.0;
         assert!(Rect {
             pos: Vec2i { x: 0, y: 0 },
             sz: src.sz
@@ -189,15 +228,13 @@ impl Image {
             };
             for (to, from) in to_cols.zip(from_cols) {
                 let ta = to.3 as f32 / 255.0;
-                let fa = from.3 as f32 / 255.0;
-                to.0 = from
-                    .0
+                let fa = from.3 as f32 / 255.0 * opacity;
+                // Colors are premultiplied, so fading scales them too
+                to.0 = ((from.0 as f32 * opacity) as u8)
                     .saturating_add((to.0 as f32 * (1.0 - fa)).round() as u8);
-                to.1 = from
-                    .1
+                to.1 = ((from.1 as f32 * opacity) as u8)
                     .saturating_add((to.1 as f32 * (1.0 - fa)).round() as u8);
-                to.2 = from
-                    .2
+                to.2 = ((from.2 as f32 * opacity) as u8)
                     .saturating_add((to.2 as f32 * (1.0 - fa)).round() as u8);
                 to.3 = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
             }
@@ -3,7 +3,14 @@ use std::rc::Rc;
 use winit;
 
 use engine;
-use engine::animations::AnimationSet;
+use engine::animations::{platformer_transitions, AnimParams, AnimStateMachine, AnimationSet};
+use engine::camera::Camera;
+use engine::draw::{self, DrawList, Font};
+use engine::eng::{pressed, Paused, Restart, Scene, SceneChange, SceneStack};
+use engine::platformer::{Platformer, PlatformerInput, PlatformerParams};
+use engine::platforms::{self, MovingPlatform, PathMode};
+use engine::projectile::{Obstacle, Projectile, ProjectileParams};
+use engine::raycast;
 use engine::sprite::{Action, Character, Sprite};
 use engine::tiles::*;
 use engine::types::*;
@@ -12,13 +19,14 @@ const PLAYER_WIDTH: i32 = 20;
 const PLAYER_HEIGHT: i32 = 32;
 pub const WIDTH: usize = 320;
 pub const HEIGHT: usize = 320;
-const TILE_SZ: i32 = 16;
+
+const MARIO: Character = Character::named("mario");
+const LUIGI: Character = Character::named("luigi");
 
 struct Assets {
     spritesheet: Rc<Image>,
     numsheet: Rc<Image>,
     textsheet: Rc<Image>,
-    tilesheet: Rc<Image>,
     tileset: Rc<Tileset>,
     tilemap: Tilemap,
     splash: Rc<Image>,
@@ -29,72 +37,82 @@ struct State {
     timer: Rect,
     p1: PlayerState,
     p2: PlayerState,
+    // A lift above the floor to take shots from
+    platforms: Vec<MovingPlatform>,
     game_over: bool,
+    draw_list: DrawList,
+    camera: Camera,
 }
 
 impl State {
-    pub fn new() -> Self {
+    pub fn new(assets: &Assets) -> Self {
         let timer = Rect {
             pos: Vec2i { x: 80, y: 4 },
             sz: Vec2i { x: 160, y: 8 },
         };
 
-        let p1 = PlayerState::new(Character::Mario);
-        let p2 = PlayerState::new(Character::Luigi);
+        let mut camera = Camera::new(Vec2i {
+            x: WIDTH as i32,
+            y: HEIGHT as i32,
+        });
+        camera.bounds = Some(assets.tilemap.world_rect());
+
+        let p1 = PlayerState::new(MARIO, "content/mario.atlas", 0, assets);
+        let p2 = PlayerState::new(LUIGI, "content/luigi.atlas", 16, assets);
+
+        // Low enough to jump onto, high enough to walk under
+        let mut lift = MovingPlatform::new(
+            Vec2i { x: 32, y: 8 },
+            vec![Vec2i { x: 96, y: 232 }, Vec2i { x: 96, y: 176 }],
+            0.5,
+            PathMode::PingPong,
+        );
+        lift.pause = 60;
 
         Self {
             time: 3600,
             timer,
             p1,
             p2,
+            platforms: vec![lift],
             game_over: false,
+            draw_list: DrawList::new(),
+            camera,
         }
     }
 }
 
 struct PlayerState {
-    player: Rect,
+    body: Platformer,
+    // Where the player goes back to after being crushed
+    spawn: Vec2i,
+    input: PlatformerInput,
     sprite: Sprite,
     animation_set: AnimationSet,
-    flip: bool,
-    jumping: bool,
+    anim: AnimStateMachine,
     speedup_factor: usize,
-    vx: f32,
-    vy: f32,
-    ax: f32,
-    ay: f32,
-    ball: Rect,
+    ball: Projectile,
     ball_shot: bool,
-    bpx: f32,
-    bpy: f32,
-    bvx: f32,
-    bvy: f32,
-    bay: f32,
     meter: Rect,
     metering: bool,
+    // The ball scores by dropping through the top of this
     basket: Rect,
+    hoop: Vec<Obstacle>,
     score: i32,
     shot_loc: i32,
     made_shots: Vec<i32>,
     splash_counter: u8,
     color: Color,
+    font: Rc<Font>,
 }
 
 impl PlayerState {
-    pub fn new(character: Character) -> Self {
-        let animation_set = AnimationSet::new(character);
-        let sprite = Sprite {
-            character,
-            action: Action::Stand,
-            animation_state: animation_set.play_animation(Action::Stand),
-            shape: Rect {
-                pos: Vec2i { x: 20, y: 20 },
-                sz: Vec2i {
-                    x: PLAYER_WIDTH as i32,
-                    y: PLAYER_HEIGHT as i32,
-                },
-            },
-        };
+    // font_row picks this player's colour of digits in the number sheet
+    pub fn new(character: Character, atlas: &str, font_row: i32, assets: &Assets) -> Self {
+        let atlas = std::fs::read_to_string(atlas).unwrap();
+        let mut animation_set =
+            AnimationSet::from_atlas(character, assets.spritesheet.clone(), &atlas);
+        animation_set.transitions = platformer_transitions();
         let speedup_factor = 5; // this acts more like a slow down factor.
         let player = Rect {
             pos: Vec2i {
@@ -107,11 +125,12 @@ impl PlayerState {
             },
         };
 
+        let mut sprite = Sprite::new(&animation_set, Action::Stand, player.pos);
+        // Keep the feet planted if a frame is a different size
+        sprite.pivot = (0.5, 1.0);
+
         // BALL
-        let ball = Rect {
-            pos: Vec2i { x: 0, y: 0 },
-            sz: Vec2i { x: 8, y: 8 },
-        };
+        let ball = Projectile::new((0.0, 0.0), (0.0, 0.0), 4.0, ProjectileParams::default());
 
         // POWER METER
         let meter = Rect {
@@ -119,44 +138,65 @@ impl PlayerState {
             sz: Vec2i { x: 4, y: 0 },
         };
 
+        // The rim runs from the pole across the middle of the hoop tile
+        let hoop = assets
+            .tilemap
+            .cells_of_kind("hoop")
+            .next()
+            .expect("the court has no hoop");
+        let corner = assets.tilemap.cell_corner(hoop);
         let basket = Rect {
-            pos: Vec2i { x: 4, y: 260 },
-            sz: Vec2i { x: 8, y: 8 },
+            pos: Vec2i {
+                x: corner.x + 3,
+                y: corner.y + 8,
+            },
+            sz: Vec2i { x: 11, y: 8 },
         };
+        let hoop = vec![
+            // The pole doubles as a backboard
+            Obstacle::Rect(Rect {
+                pos: corner,
+                sz: Vec2i { x: 3, y: 32 },
+            }),
+            // The front of the rim
+            Obstacle::Circle {
+                center: ((basket.pos.x + basket.sz.x) as f32, basket.pos.y as f32),
+                radius: 1.0,
+            },
+        ];
 
         let color: Color;
-        if character == Character::Mario {
+        if character == MARIO {
             color = (255, 0, 0, 255);
         } else {
             color = (0, 255, 0, 255);
         }
 
         Self {
-            player: player,
+            body: Platformer::new(player, PlatformerParams::default()),
+            spawn: player.pos,
+            input: PlatformerInput::default(),
             sprite: sprite,
+            anim: animation_set.state_machine(),
             animation_set: animation_set,
-            flip: false,
-            jumping: false,
             speedup_factor: speedup_factor,
-            vx: 0.0,
-            vy: 0.0,
-            ax: 0.0,
-            ay: 0.2,
             ball,
             ball_shot: false,
-            bpx: 1.0,
-            bpy: 1.0,
-            bvx: 0.0,
-            bvy: 0.0,
-            bay: 0.2,
             meter,
             metering: false,
             basket,
+            hoop,
             score: 0,
             shot_loc: 0,
             made_shots: vec![],
             splash_counter: 0,
             color,
+            font: Rc::new(Font {
+                image: assets.numsheet.clone(),
+                glyph_sz: Vec2i { x: 16, y: 16 },
+                origin: Vec2i { x: 0, y: font_row },
+                chars: "0123456789".to_string(),
+            }),
         }
     }
 }
@@ -167,58 +207,34 @@ fn main() {
     engine::eng::go::<Game>();
 }
 
+// The ball as it leaves the player's hands, thrown as hard as the meter says
+fn shot(state: &PlayerState) -> Projectile {
+    let mut ball = state.ball.clone();
+    let pos = state.body.rect.pos;
+    ball.pos = (pos.x as f32 + ball.radius, pos.y as f32 + ball.radius);
+    ball.launch((-6.0 * (state.meter.sz.y as f32 / 64.0), -4.0));
+    ball
+}
+
 // [Up, Left, Right, Down]
 fn update_player(state: &mut PlayerState, now_keys: &[bool], prev_keys: &[bool]) {
-    if now_keys[0] && !prev_keys[0] && !state.jumping {
-        state.vy = -5.0;
-        state.jumping = true;
-        // Only update the animation if it was previously something else.
-        if state.sprite.action != Action::Jump {
-            state
-                .sprite
-                .set_animation(state.animation_set.play_animation(Action::Jump));
-        }
-    }
-
+    // Down is the shot meter, so there's no dropping through platforms
+    state.input = PlatformerInput {
+        left: now_keys[1],
+        right: now_keys[2],
+        jump: now_keys[0],
+        jump_pressed: now_keys[0] && !prev_keys[0],
+        down: false,
+    };
     if now_keys[1] {
-        if state.vx > -2.0 {
-            state.ax = -0.2;
-        } else {
-            state.ax = 0.0
-        }
-        state.flip = true;
-        // Only update the animation if it was previously something else.
-        if state.sprite.action != Action::Walk {
-            state
-                .sprite
-                .set_animation(state.animation_set.play_animation(Action::Walk));
-        }
+        state.sprite.flip = true;
     } else if now_keys[2] {
-        if state.vx < 2.0 {
-            state.ax = 0.2;
-        } else {
-            state.ax = 0.0
-        }
-        state.flip = false;
-        // Only update the animation if it was previously something else.
-        if state.sprite.action != Action::Walk {
-            state
-                .sprite
-                .set_animation(state.animation_set.play_animation(Action::Walk));
-        }
-    } else {
-        if state.vx > 0.1 {
-            state.ax = -0.1
-        } else if state.vx < -0.1 {
-            state.ax = 0.1
-        } else {
-            state.ax = 0.0
-        }
+        state.sprite.flip = false;
     }
 
     if now_keys[3] && !state.ball_shot {
-        state.meter.pos.x = state.player.pos.x + state.player.sz.x;
-        state.meter.pos.y = state.player.pos.y + state.player.sz.y / 2 - state.meter.sz.y;
+        state.meter.pos.x = state.body.rect.pos.x + state.body.rect.sz.x;
+        state.meter.pos.y = state.body.rect.pos.y + state.body.rect.sz.y / 2 - state.meter.sz.y;
 
         state.metering = true;
 
@@ -228,124 +244,70 @@ fn update_player(state: &mut PlayerState, now_keys: &[bool], prev_keys: &[bool])
     }
 
     if !now_keys[3] && prev_keys[3] && !state.ball_shot {
-        state.ball.pos = state.player.pos;
-        state.bpx = state.player.pos.x as f32;
-        state.bpy = state.player.pos.y as f32;
-        state.bvx = -6.0 * (state.meter.sz.y as f32 / 64.0);
-        state.bvy = -4.0;
+        state.ball = shot(state);
         state.metering = false;
         state.meter.sz.y = 0;
         state.ball_shot = true;
-        state.shot_loc = state.player.pos.x / 16;
+        state.shot_loc = state.body.rect.pos.x / 16;
     }
 
-    if state.vx as i32 == 0 && state.vy as i32 == 0 {
-        state
-            .sprite
-            .set_animation(state.animation_set.play_animation(Action::Stand));
-    }
+    // The animation state machine picks Stand/Walk/Jump from these
+    state.anim.params = AnimParams {
+        speed: state.body.vx.abs(),
+        grounded: state.body.grounded(),
+        facing_left: state.sprite.flip,
+    };
 }
 
-fn render_player(state: &mut PlayerState, assets: &mut Assets, fb2d: &mut Image) {
-    fb2d.bitblt(
-        &assets.spritesheet,
-        state.sprite.play_animation(&state.speedup_factor),
-        state.player.pos,
-        state.flip,
+fn render_player(
+    state: &mut PlayerState,
+    assets: &mut Assets,
+    list: &mut DrawList,
+    camera: &mut Camera,
+    platforms: &[MovingPlatform],
+) {
+    state.sprite.shape.pos = state.body.rect.pos;
+    state.anim.tick(
+        &state.animation_set,
+        &mut state.sprite,
+        &state.speedup_factor,
     );
+    list.sprite(&state.sprite);
 
-    state.vx += state.ax;
-    state.vy += state.ay;
-    state.player.move_by(state.vx as i32, state.vy as i32);
-
-    let mut ovs = vec![];
-    for i in 0..3 {
-        for j in 0..3 {
-            let p = Vec2i {
-                x: state.player.pos.x + i * (state.player.sz.x / 2),
-                y: state.player.pos.y + j * (state.player.sz.y / 2),
-            };
-            let r = assets.tilemap.tile_at(p);
-            if r.1.solid {
-                let mut ov = Vec2i { x: 0, y: 0 };
-                if state.vx > 0.0 {
-                    ov.x = r.0.x - (state.player.pos.x + PLAYER_WIDTH);
-                } else {
-                    ov.x = (r.0.x + TILE_SZ) - state.player.pos.x;
-                }
-
-                if state.vy > 0.0 {
-                    ov.y = r.0.y - (state.player.pos.y + PLAYER_HEIGHT);
-                } else {
-                    ov.y = (r.0.y + TILE_SZ) - state.player.pos.y;
-                }
-
-                ovs.push(ov);
-            }
-        }
-    }
-
-    let mut disps = Vec2i { x: 0, y: 0 };
-    let mut resolved = false;
-    for ov in ovs.iter() {
-        // Touching but not overlapping
-        if ov.x == 0 && ov.y == 0 {
-            resolved = true;
-            // Maybe track "I'm touching it on this side or that side"
-            break;
-        }
-        // Is this more of a horizontal collision... (and we are allowed to displace horizontally)
-        if ov.x.abs() <= ov.y.abs() && ov.x.signum() != -disps.x.signum() {
-            // Record that we moved by o.x, to avoid contradictory moves later
-            disps.x += ov.x;
-            // Actually move player pos
-            state.player.pos.x += ov.x;
-            state.vx = 0.0;
-            // Mark collision for the player as resolved.
-            resolved = true;
-            break;
-            // or is it more of a vertical collision (and we are allowed to displace vertically)
-        } else if ov.y.abs() <= ov.x.abs() && ov.y.signum() != -disps.y.signum() {
-            disps.y += ov.y;
-            state.player.pos.y += ov.y;
-            state.vy = 0.0;
-            state.jumping = false;
-            resolved = true;
-            break;
-        } else {
-            // otherwise, we can't actually handle this displacement because we had a contradictory
-            // displacement earlier in the frame.
-        }
+    if state.body.ride(&assets.tilemap, platforms) {
+        state.body.teleport(state.spawn);
     }
-    // Couldn't resolve collision, player must be squashed or trapped (e.g. by a moving platform)
-    if !resolved {
-        // In your game, this might mean killing the player character or moving them somewhere else
+    let solids = platforms::solids(platforms);
+    state
+        .body
+        .update_among(&assets.tilemap, &solids, &state.input);
+
+    // check to make sure player is in the court
+    let court = assets.tilemap.world_rect();
+    if state.body.rect.pos.x < court.pos.x {
+        state.body.rect.pos.x = court.pos.x
     }
-
-    // check to make sure player is in screen bounds
-    if state.player.pos.x < 0 {
-        state.player.pos.x = 0
-    }
-    if state.player.pos.x > WIDTH as i32 - state.player.sz.x {
-        state.player.pos.x = WIDTH as i32 - state.player.sz.x
+    if state.body.rect.pos.x > court.pos.x + court.sz.x - state.body.rect.sz.x {
+        state.body.rect.pos.x = court.pos.x + court.sz.x - state.body.rect.sz.x
     }
-    if state.player.pos.y < 0 {
-        state.player.pos.y = 0;
+    if state.body.rect.pos.y < court.pos.y {
+        state.body.rect.pos.y = court.pos.y;
     }
-    if state.player.pos.y > HEIGHT as i32 - state.player.sz.y {
-        state.player.pos.y = HEIGHT as i32 - state.player.sz.y;
+    if state.body.rect.pos.y > court.bottom() - state.body.rect.sz.y {
+        state.body.rect.pos.y = court.bottom() - state.body.rect.sz.y;
     }
 
     // BALL CODE
     if state.ball_shot {
-        if state.basket.contains_point({
-            Vec2i {
-                x: state.bpx as i32 + state.ball.sz.x / 2,
-                y: state.bpy as i32 + state.ball.sz.y / 2,
-            }
-        }) {
+        state.ball.step(&assets.tilemap, &state.hoop);
+        let (x0, x1) = (
+            state.basket.pos.x as f32,
+            (state.basket.pos.x + state.basket.sz.x) as f32,
+        );
+        if state.ball.crossed_down(state.basket.pos.y as f32, x0, x1) {
             state.ball_shot = false;
             state.splash_counter = 30;
+            camera.shake(3, 20);
 
             if !state.made_shots.contains(&state.shot_loc) {
                 state.score += state.shot_loc;
@@ -353,32 +315,43 @@ fn render_player(state: &mut PlayerState, assets: &mut Assets, fb2d: &mut Image)
             }
         }
 
-        if (state.bpx as i32) + state.ball.sz.x > 0
-            && (state.bpy as i32) < (HEIGHT as i32)
-            && state.ball_shot
-        {
-            state.bvy += state.bay;
-
-            state.bpx += state.bvx;
-            state.bpy += state.bvy;
-
-            state.ball.pos.x = state.bpx as i32;
-            state.ball.pos.y = state.bpy as i32;
-
-            fb2d.draw_ball(&state.ball, state.color);
+        if court.overlaps(state.ball.rect()) && !state.ball.resting && state.ball_shot {
+            list.ball(draw::WORLD + 1, state.ball.rect(), state.color);
         } else {
             state.ball_shot = false;
-            state.bvx = 0.0;
-            state.bvy = 0.0;
         }
     }
 
     if state.metering {
-        fb2d.draw_rect(&state.meter, state.color);
+        list.rect(draw::WORLD + 1, state.meter, state.color);
+        // Dots along where the ball would go if it were let go now,
+        // greyed out when the court is in the way of the hoop
+        let ball = shot(state);
+        let rim = (
+            (state.basket.pos.x + state.basket.sz.x / 2) as f32,
+            state.basket.pos.y as f32,
+        );
+        let color = if raycast::line_of_sight(&assets.tilemap, ball.pos, rim) {
+            state.color
+        } else {
+            (128, 128, 128, 255)
+        };
+        let path = ball.predict(&assets.tilemap, &state.hoop, 90);
+        for &(x, y) in path.iter().step_by(4) {
+            let dot = Rect {
+                pos: Vec2i {
+                    x: x as i32 - 1,
+                    y: y as i32 - 1,
+                },
+                sz: Vec2i { x: 2, y: 2 },
+            };
+            list.rect(draw::WORLD + 1, dot, color);
+        }
     }
 
     if state.splash_counter > 0 {
-        fb2d.bitblt(
+        list.blit(
+            draw::WORLD + 1,
             &assets.splash,
             Rect {
                 pos: Vec2i { x: 16, y: 0 },
@@ -390,73 +363,23 @@ fn render_player(state: &mut PlayerState, assets: &mut Assets, fb2d: &mut Image)
         state.splash_counter -= 1;
     }
 
-    let row: i32;
-    let offset: i32;
-    if state.sprite.character == Character::Mario {
-        row = 0;
-        offset = 0;
+    let offset = if state.sprite.character == MARIO {
+        0
     } else {
-        row = 16;
-        offset = WIDTH as i32 - 48;
-    }
-
-    let score1 = state.score % 10;
-    let score10 = (state.score / 10) % 10;
-    let score100 = state.score / 100;
-
-    fb2d.bitblt(
-        &assets.numsheet,
-        Rect {
-            pos: Vec2i {
-                x: score1 * 16,
-                y: row,
-            },
-            sz: Vec2i { x: 16, y: 16 },
-        },
-        Vec2i {
-            x: offset + 32,
-            y: 0,
-        },
-        false,
-    );
-
-    fb2d.bitblt(
-        &assets.numsheet,
-        Rect {
-            pos: Vec2i {
-                x: score10 * 16,
-                y: row,
-            },
-            sz: Vec2i { x: 16, y: 16 },
-        },
-        Vec2i {
-            x: offset + 16,
-            y: 0,
-        },
-        false,
-    );
-
-    fb2d.bitblt(
-        &assets.numsheet,
-        Rect {
-            pos: Vec2i {
-                x: score100 * 16,
-                y: row,
-            },
-            sz: Vec2i { x: 16, y: 16 },
-        },
+        WIDTH as i32 - 48
+    };
+    list.text(
+        draw::HUD,
+        &state.font,
+        &format!("{:03}", state.score % 1000),
         Vec2i { x: offset, y: 0 },
-        false,
     );
 }
 
 impl engine::eng::Game for Game {
     type Assets = Assets;
-    type State = State;
-    fn new() -> (State, Assets) {
-        let tilesheet = Rc::new(Image::from_file(std::path::Path::new(
-            "content/tilesheet.png",
-        )));
+    type State = SceneStack<Assets>;
+    fn new() -> (SceneStack<Assets>, Assets) {
         let spritesheet = Rc::new(Image::from_file(std::path::Path::new(
             "content/spritesheet.png",
         )));
@@ -466,64 +389,60 @@ impl engine::eng::Game for Game {
         let textsheet = Rc::new(Image::from_file(std::path::Path::new(
             "content/textsheet.png",
         )));
-        let tileset = Rc::new(Tileset::new(
-            vec![
-                Tile { solid: true },
-                Tile { solid: true },
-                Tile { solid: true },
-                Tile { solid: true },
-                Tile { solid: true },
-                Tile { solid: false },
-                Tile { solid: false },
-                Tile { solid: false },
-                Tile { solid: false },
-                Tile { solid: false },
-            ],
-            tilesheet.clone(),
-        ));
-        let map = Tilemap::new(
-            Vec2i { x: 0, y: 0 },
-            (20, 20),
-            tileset.clone(),
-            vec![
-                6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
-                6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
-                6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
-                6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
-                6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
-                6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
-                6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
-                6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
-                6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
-                6, 0, 0, 0, 0, 0, 0, 0, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 0, 0, 0, 0, 0, 0, 0, 0,
-                6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 6, 6, 6, 6, 6, 6, 6, 6,
-                6, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9, 6, 6, 6, 6, 6, 6, 6, 6, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 8, 5, 5, 5, 5, 5, 5, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 2, 2, 2,
-                2, 2, 2, 3, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
-                4, 4, 4, 4, 4, 4, 4, 4,
-            ],
-        );
+        // The court is authored in Tiled; see content/court.tmx
+        let mut map = Tilemap::from_tiled(std::path::Path::new("content/court.tmx"));
+        // Nobody leaves the court
+        map.out_of_bounds = Tile::solid();
+        let tileset = map.tileset().clone();
 
         let splash = Rc::new(Image::from_file(std::path::Path::new("content/splash.png")));
         let assets = Assets {
             spritesheet,
             numsheet,
             textsheet,
-            tilesheet,
             tileset,
             tilemap: map,
             splash,
         };
-        let state = State::new();
-        (state, assets)
+        let state = State::new(&assets);
+        (SceneStack::new(Box::new(state)), assets)
     }
 
-    fn update(state: &mut State, _assets: &mut Assets, now_keys: &[bool], prev_keys: &[bool]) {
-        if state.game_over {
-            return;
-        }
+    fn update(
+        stack: &mut SceneStack<Assets>,
+        assets: &mut Assets,
+        now_keys: &[bool],
+        prev_keys: &[bool],
+    ) {
+        stack.update(assets, now_keys, prev_keys);
+    }
+
+    fn render(stack: &mut SceneStack<Assets>, assets: &mut Assets, fb2d: &mut Image) {
+        stack.render(assets, fb2d);
+    }
+}
+
+impl Scene<Assets> for State {
+    fn update(
+        &mut self,
+        _assets: &mut Assets,
+        now_keys: &[bool],
+        prev_keys: &[bool],
+    ) -> SceneChange<Assets> {
         use winit::event::VirtualKeyCode;
 
+        // Leave the final score up until someone wants another game
+        if self.game_over {
+            return SceneChange::Replace(Box::new(Restart::new(
+                VirtualKeyCode::Space as usize,
+                30,
+                |assets: &mut Assets| Box::new(State::new(assets)),
+            )));
+        }
+        if pressed(VirtualKeyCode::P as usize, now_keys, prev_keys) {
+            return SceneChange::Push(Box::new(Paused::new(VirtualKeyCode::P as usize)));
+        }
+
         let p1_now_keys = vec![
             now_keys[VirtualKeyCode::W as usize],
             now_keys[VirtualKeyCode::A as usize],
@@ -552,36 +471,73 @@ impl engine::eng::Game for Game {
             prev_keys[VirtualKeyCode::Down as usize],
         ];
 
-        update_player(&mut state.p1, &p1_now_keys, &p1_prev_keys);
-        update_player(&mut state.p2, &p2_now_keys, &p2_prev_keys);
+        update_player(&mut self.p1, &p1_now_keys, &p1_prev_keys);
+        update_player(&mut self.p2, &p2_now_keys, &p2_prev_keys);
+        SceneChange::None
     }
 
-    fn render(state: &mut State, assets: &mut Assets, fb2d: &mut Image) {
-        if state.game_over {
+    fn render(&mut self, assets: &mut Assets, fb2d: &mut Image) {
+        if self.game_over {
             return;
         }
-        assets.tilemap.draw(fb2d);
-        render_player(&mut state.p1, assets, fb2d);
-        render_player(&mut state.p2, assets, fb2d);
-
-        if state.time > 0 {
-            state.time -= 1;
-            let tw = (160.0 * (state.time as f32 / 3600.0)) as i32;
-            state.timer.sz.x = tw + (tw % 2);
-            state.timer.pos.x = (WIDTH as i32) / 2 - state.timer.sz.x / 2;
-            fb2d.draw_rect(&state.timer, (255, 255, 255, 255));
+        // Keep both players in view
+        let (a, b) = (self.p1.body.rect, self.p2.body.rect);
+        let x = a.pos.x.min(b.pos.x);
+        let y = a.pos.y.min(b.pos.y);
+        self.camera.follow(Rect {
+            pos: Vec2i { x, y },
+            sz: Vec2i {
+                x: (a.pos.x + a.sz.x).max(b.pos.x + b.sz.x) - x,
+                y: a.bottom().max(b.bottom()) - y,
+            },
+        });
+        self.camera.tick();
+        assets.tileset.tick();
+        for platform in &mut self.platforms {
+            platform.step();
+        }
+        self.draw_list.set_camera(&self.camera);
+
+        assets.tilemap.queue_draw(&mut self.draw_list, &self.camera);
+        for platform in &self.platforms {
+            self.draw_list
+                .rect(draw::WORLD, platform.rect, (160, 160, 160, 255));
+        }
+        render_player(
+            &mut self.p1,
+            assets,
+            &mut self.draw_list,
+            &mut self.camera,
+            &self.platforms,
+        );
+        render_player(
+            &mut self.p2,
+            assets,
+            &mut self.draw_list,
+            &mut self.camera,
+            &self.platforms,
+        );
+
+        if self.time > 0 {
+            self.time -= 1;
+            let tw = (160.0 * (self.time as f32 / 3600.0)) as i32;
+            self.timer.sz.x = tw + (tw % 2);
+            self.timer.pos.x = (WIDTH as i32) / 2 - self.timer.sz.x / 2;
+            self.draw_list
+                .rect(draw::HUD, self.timer, (255, 255, 255, 255));
         } else {
-            state.game_over = true;
+            self.game_over = true;
             let winner: i32;
-            if state.p1.score > state.p2.score {
+            if self.p1.score > self.p2.score {
                 winner = 1;
-            } else if state.p1.score < state.p2.score {
+            } else if self.p1.score < self.p2.score {
                 winner = 2;
             } else {
                 winner = 0;
             }
 
-            fb2d.bitblt(
+            self.draw_list.blit(
+                draw::HUD,
                 &assets.textsheet,
                 Rect {
                     pos: Vec2i {
@@ -594,5 +550,6 @@ impl engine::eng::Game for Game {
                 false,
             )
         }
+        self.draw_list.flush(fb2d);
     }
 }
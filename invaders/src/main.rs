@@ -3,8 +3,15 @@ use std::rc::Rc;
 use winit;
 
 use engine;
-use engine::animations::AnimationSet;
+use engine::animations::{Animation, AnimationSet};
+use engine::camera::Camera;
+use engine::draw::{self, DrawList};
+use engine::ecs::{self, Entity, Fill, Velocity, World};
+use engine::eng::{pressed, Paused, Restart, Scene, SceneChange, SceneStack};
+use engine::mask::SpriteMasks;
+use engine::overlap::{Layers, OverlapEvent, Overlaps};
 use engine::sprite::{Action, Character, Sprite};
+use engine::tiles::{Tile, TileID, Tilemap, Tileset};
 use engine::types::*;
 
 const PLAYER_WIDTH: i32 = 32;
@@ -17,11 +24,6 @@ const BULLET_VELO: i32 = 1;
 const RED: Color = (181, 49, 32, 255);
 const BLUE: Color = (74, 206, 222, 255);
 
-const SS_PLAYER: Rect = Rect {
-    pos: Vec2i { x: 32, y: 16 },
-    sz: Vec2i { x: 32, y: 16 },
-};
-
 const SS_WIN: Rect = Rect {
     pos: Vec2i { x: 0, y: 0 },
     sz: Vec2i { x: 80, y: 16 },
@@ -32,160 +34,285 @@ const SS_LOSE: Rect = Rect {
     sz: Vec2i { x: 80, y: 16 },
 };
 
+// Collision layers
+const PLAYER_LAYER: Layers = 1;
+const ENEMY_LAYER: Layers = 2;
+const BLOCKER_LAYER: Layers = 4;
+const PLAYER_SHOT: Layers = 8;
+const ENEMY_SHOT: Layers = 16;
+
+/// Everything that can run into something else
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+enum Thing {
+    Player,
+    Blocker((i32, i32)),
+    /// An enemy or a bullet
+    Entity(Entity),
+}
+
+const INVADER: Character = Character::named("invader");
+const ENEMY1: Character = Character::named("enemy1");
+const ENEMY2: Character = Character::named("enemy2");
+
+const ENEMY_SZ: Vec2i = Vec2i { x: 16, y: 16 };
+const INVADER_SZ: Vec2i = Vec2i { x: 22, y: 16 };
+
+fn player_animations(spritesheet: &Rc<Image>) -> AnimationSet {
+    let mut set = AnimationSet::new(INVADER, spritesheet.clone());
+    set.add_animation(
+        Action::Die,
+        Animation {
+            frames: vec![
+                Rect {
+                    pos: Vec2i { x: 37, y: 32 },
+                    sz: INVADER_SZ,
+                },
+                Rect {
+                    pos: Vec2i { x: 37, y: 50 },
+                    sz: INVADER_SZ,
+                },
+            ],
+            frame_timings: vec![0, 10],
+            loops: true,
+        },
+    );
+    set.add_animation(
+        Action::Glide,
+        Animation::strip(Vec2i { x: 37, y: 16 }, INVADER_SZ, 1, true),
+    );
+    set
+}
+
+fn enemy_animations(character: Character, spritesheet: &Rc<Image>) -> AnimationSet {
+    let row = if character == ENEMY2 { 16 } else { 0 };
+    let mut set = AnimationSet::new(character, spritesheet.clone());
+    set.add_animation(
+        Action::Glide,
+        Animation::strip(Vec2i { x: 0, y: row }, ENEMY_SZ, 2, true),
+    );
+    set.add_animation(
+        Action::Die,
+        Animation::strip(Vec2i { x: 32, y: 0 }, ENEMY_SZ, 1, true),
+    );
+    set
+}
+
 struct Assets {
     spritesheet: Rc<Image>,
     enemy1_animation_set: AnimationSet,
     enemy2_animation_set: AnimationSet,
     player_animation_set: AnimationSet,
-    winlose_spritesheet: Image,
+    player_masks: SpriteMasks,
+    winlose_spritesheet: Rc<Image>,
 }
 
 struct State {
     player_sprite: Sprite,
-    player_bullets: Vec<Rect>,
     vx: f32,
     ax: f32,
 
-    enemies: Vec<Enemy>,
-    enemy_bullets: Vec<Rect>,
+    // Enemies and bullets
+    entities: World,
     evx: i32,
 
-    blockers: Vec<Blocker>,
+    blockers: Tilemap,
     shooting_timeout: u8,
 
-    game_over: u8,
+    world: Overlaps<Thing>,
+
+    game_over: bool,
+    draw_list: DrawList,
+    camera: Camera,
 }
 
 impl State {
-    pub fn new() -> Self {
+    pub fn new(spritesheet: &Rc<Image>) -> Self {
         // SPRITES
-        let animation_set = AnimationSet::new(Character::SpaceInvader);
-        let player_sprite = Sprite {
-            character: Character::SpaceInvader,
-            action: Action::Glide,
-            animation_state: animation_set.play_animation(Action::Glide),
-            shape: Rect {
-                pos: Vec2i {
-                    x: WIDTH / 2 - PLAYER_WIDTH / 2,
-                    y: HEIGHT - PLAYER_HEIGHT * 3,
-                },
-                sz: Vec2i {
-                    x: PLAYER_WIDTH,
-                    y: PLAYER_HEIGHT,
-                },
+        let animation_set = player_animations(spritesheet);
+        let mut player_sprite = Sprite::new(
+            &animation_set,
+            Action::Glide,
+            Vec2i {
+                x: WIDTH / 2 - PLAYER_WIDTH / 2,
+                y: HEIGHT - PLAYER_HEIGHT * 3,
             },
+        );
+        // The ship art is narrower than the player's box; keep it centred
+        player_sprite.shape.sz = Vec2i {
+            x: PLAYER_WIDTH,
+            y: PLAYER_HEIGHT,
         };
+        player_sprite.pivot = (0.5, 0.0);
 
-        let mut enemies = vec![];
+        let mut entities = World::new();
+        entities.register::<Enemy>();
+        entities.register::<Bullet>();
+        let blockers = blocker_map();
 
+        let mut world = Overlaps::new(Vec2i { x: 32, y: 32 });
+        world.insert(Thing::Player, player_sprite.frame_rect(), PLAYER_LAYER, 0);
         for y in 0..2 {
             for x in 0..8 {
-                enemies.push(Enemy::new((x + y) % 2, Vec2i { x, y }));
+                let enemy = Enemy::spawn((x + y) % 2, Vec2i { x, y }, spritesheet, &mut entities);
+                let rect = *entities.get::<Rect>(enemy).unwrap();
+                world.insert(Thing::Entity(enemy), rect, ENEMY_LAYER, 0);
             }
         }
-        let mut blockers = vec![];
-        for y in 0..2 {
-            for x in 0..12 {
-                blockers.push(Blocker::new(Vec2i { x, y }));
+        let (w, h) = blockers.size();
+        for y in 0..h as i32 {
+            for x in 0..w as i32 {
+                if blockers.tile_in_cell((x, y)).is_solid() {
+                    let rect = Rect {
+                        pos: blockers.cell_corner((x, y)),
+                        sz: BLOCKER_SZ,
+                    };
+                    world.insert(Thing::Blocker((x, y)), rect, BLOCKER_LAYER, 0);
+                }
             }
         }
 
         State {
             player_sprite,
-            player_bullets: vec![],
             vx: 0.0,
             ax: 0.0,
 
-            enemies,
-            enemy_bullets: vec![],
+            entities,
             evx: 1,
 
             blockers,
             shooting_timeout: 0,
 
-            game_over: 0,
+            world,
+
+            game_over: false,
+            draw_list: DrawList::new(),
+            camera: Camera::new(Vec2i {
+                x: WIDTH,
+                y: HEIGHT,
+            }),
+        }
+    }
+
+    fn add_bullet(&mut self, rect: Rect, from_player: bool) {
+        let bullet = self.entities.spawn();
+        let (dy, color) = if from_player {
+            (-2 * BULLET_VELO, BLUE)
+        } else {
+            (BULLET_VELO, RED)
+        };
+        self.entities.insert(bullet, rect);
+        self.entities
+            .insert(bullet, Velocity(Vec2i { x: 0, y: dy }));
+        self.entities.insert(
+            bullet,
+            Fill {
+                layer: draw::WORLD,
+                color,
+            },
+        );
+        self.entities.insert(bullet, Bullet { from_player });
+        let (layer, mask) = if from_player {
+            (PLAYER_SHOT, ENEMY_LAYER | BLOCKER_LAYER)
+        } else {
+            (ENEMY_SHOT, PLAYER_LAYER | BLOCKER_LAYER)
+        };
+        self.world.insert(Thing::Entity(bullet), rect, layer, mask);
+    }
+
+    /// Get rid of everything despawned, collision bodies and all
+    fn sweep(&mut self) {
+        for entity in self.entities.maintain() {
+            self.world.remove(Thing::Entity(entity));
         }
     }
 }
 
 struct Game {}
 
+// Enemies also have a Rect, Velocity and Sprite
 struct Enemy {
     style: i32,
-    sprite: Sprite,
-    rect: Rect,
-    alive: bool,
+}
+
+// Bullets also have a Rect, Velocity and Fill
+struct Bullet {
+    from_player: bool,
 }
 
 impl Enemy {
-    pub fn new(style: i32, index: Vec2i) -> Self {
+    pub fn spawn(
+        style: i32,
+        index: Vec2i,
+        spritesheet: &Rc<Image>,
+        entities: &mut World,
+    ) -> Entity {
         assert!(index.x < 8, "{} is out of range 8", index.x);
         assert!(index.y < 2, "{} is out of range 2", index.y);
-        let character = if style == 0 {
-            Character::SpaceInvaderEnemy1
-        } else if style == 1 {
-            Character::SpaceInvaderEnemy2
-        } else {
-            Character::SpaceInvaderEnemy1
+        let character = if style == 1 { ENEMY2 } else { ENEMY1 };
+        let animation_set = enemy_animations(character, spritesheet);
+        let pos = Vec2i {
+            x: 64 + 16 * index.x + (16 * index.x / 2),
+            y: 32 + 32 * index.y,
         };
-        let animation_set = AnimationSet::new(character);
-        let sprite = Sprite {
-            character: character,
-            action: Action::Glide,
-            animation_state: animation_set.play_animation(Action::Glide),
-            shape: Rect {
-                pos: Vec2i {
-                    x: 64 + 16 * index.x + (16 * index.x / 2),
-                    y: 32 + 32 * index.y,
-                },
+        let enemy = entities.spawn();
+        entities.insert(enemy, Sprite::new(&animation_set, Action::Glide, pos));
+        entities.insert(
+            enemy,
+            Rect {
+                pos,
                 sz: Vec2i { x: 16, y: 16 },
             },
-        };
-        Self {
-            style,
-            sprite: sprite,
-            rect: Rect {
-                pos: Vec2i {
-                    x: 64 + 16 * index.x + (16 * index.x / 2),
-                    y: 32 + 32 * index.y,
-                },
-                sz: Vec2i { x: 16, y: 16 },
-            },
-            alive: true,
-        }
+        );
+        entities.insert(enemy, Velocity(Vec2i { x: 1, y: 0 }));
+        entities.insert(enemy, Self { style });
+        enemy
     }
 
-    pub fn shoot(&self) -> Rect {
+    pub fn shoot(rect: Rect) -> Rect {
         Rect {
             pos: Vec2i {
-                x: self.rect.pos.x + self.rect.sz.x / 2 - 1,
-                y: self.rect.pos.y + self.rect.sz.y,
+                x: rect.pos.x + rect.sz.x / 2 - 1,
+                y: rect.pos.y + rect.sz.y,
             },
             sz: Vec2i { x: 2, y: 8 },
         }
     }
 }
 
-struct Blocker {
-    rect: Rect,
-    alive: bool,
-}
-
-impl Blocker {
-    fn new(index: Vec2i) -> Self {
-        assert!(index.x < 12, "{} is out of range 12", index.x);
-        assert!(index.y < 2, "{} is out of range 2", index.y);
-        Self {
-            rect: Rect {
+const BLOCKER: TileID = TileID::new(0);
+const BLOCKER_SZ: Vec2i = Vec2i { x: 16, y: 16 };
+
+/// Three bunkers of 4x2 solid tiles, chipped away one tile per bullet
+fn blocker_map() -> Tilemap {
+    let mut image = Image::new(BLOCKER_SZ);
+    image.clear(BLUE);
+    let tileset = Rc::new(Tileset::new(
+        vec![Tile::solid()],
+        Rc::new(image),
+        BLOCKER_SZ,
+    ));
+    let mut map = Tilemap::empty(
+        Vec2i { x: 0, y: 0 },
+        (
+            (WIDTH / BLOCKER_SZ.x) as usize,
+            (HEIGHT / BLOCKER_SZ.y) as usize,
+        ),
+        tileset,
+    );
+    for bunker in 0..3 {
+        map.fill_rect(
+            0,
+            Rect {
                 pos: Vec2i {
-                    x: 32 + 16 * index.x + 32 * (index.x / 4),
-                    y: HEIGHT - 96 + 16 * index.y,
+                    x: 2 + 6 * bunker,
+                    y: 14,
                 },
-                sz: Vec2i { x: 16, y: 16 },
+                sz: Vec2i { x: 4, y: 2 },
             },
-            alive: true,
-        }
+            BLOCKER,
+        );
     }
+    map
 }
 
 fn main() {
@@ -194,252 +321,289 @@ fn main() {
 
 impl engine::eng::Game for Game {
     type Assets = Assets;
-    type State = State;
-    fn new() -> (State, Assets) {
+    type State = SceneStack<Assets>;
+    fn new() -> (SceneStack<Assets>, Assets) {
         let spritesheet = Rc::new(Image::from_file(std::path::Path::new(
             "content/spritesheet.png",
         )));
-        let wl_spritesheet = Image::from_file(std::path::Path::new("content/winlose.png"));
+        let wl_spritesheet = Rc::new(Image::from_file(std::path::Path::new(
+            "content/winlose.png",
+        )));
+        let player_animation_set = player_animations(&spritesheet);
         let assets = Assets {
+            enemy1_animation_set: enemy_animations(ENEMY1, &spritesheet),
+            enemy2_animation_set: enemy_animations(ENEMY2, &spritesheet),
+            player_masks: SpriteMasks::new(&player_animation_set),
+            player_animation_set,
             spritesheet,
-            enemy1_animation_set: AnimationSet::new(Character::SpaceInvaderEnemy1),
-            enemy2_animation_set: AnimationSet::new(Character::SpaceInvaderEnemy2),
-            player_animation_set: AnimationSet::new(Character::SpaceInvader),
             winlose_spritesheet: wl_spritesheet,
         };
-        let state = State::new();
-        (state, assets)
+        let state = State::new(&assets.spritesheet);
+        (SceneStack::new(Box::new(state)), assets)
     }
 
-    fn update(state: &mut State, _assets: &mut Assets, now_keys: &[bool], prev_keys: &[bool]) {
+    fn update(
+        stack: &mut SceneStack<Assets>,
+        assets: &mut Assets,
+        now_keys: &[bool],
+        prev_keys: &[bool],
+    ) {
+        stack.update(assets, now_keys, prev_keys);
+    }
+
+    fn render(stack: &mut SceneStack<Assets>, assets: &mut Assets, fb2d: &mut Image) {
+        stack.render(assets, fb2d);
+    }
+}
+
+impl Scene<Assets> for State {
+    fn update(
+        &mut self,
+        _assets: &mut Assets,
+        now_keys: &[bool],
+        prev_keys: &[bool],
+    ) -> SceneChange<Assets> {
         use winit::event::VirtualKeyCode;
 
+        // Leave the win or lose message up until the player wants another go
+        if self.game_over {
+            return SceneChange::Replace(Box::new(Restart::new(
+                VirtualKeyCode::Space as usize,
+                30,
+                |assets: &mut Assets| Box::new(State::new(&assets.spritesheet)),
+            )));
+        }
+        if pressed(VirtualKeyCode::P as usize, now_keys, prev_keys) {
+            return SceneChange::Push(Box::new(Paused::new(VirtualKeyCode::P as usize)));
+        }
+
         // LEFT KEY
         if now_keys[VirtualKeyCode::Left as usize] {
-            if state.vx > -1.0 {
-                state.ax = -0.2;
+            if self.vx > -1.0 {
+                self.ax = -0.2;
             } else {
-                state.ax = 0.0
+                self.ax = 0.0
             }
         }
         // RIGHT KEY
         if now_keys[VirtualKeyCode::Right as usize] {
-            if state.vx < 1.0 {
-                state.ax = 0.2;
+            if self.vx < 1.0 {
+                self.ax = 0.2;
             } else {
-                state.ax = 0.0
+                self.ax = 0.0
             }
         }
         // BRAKING FORCE
         if !now_keys[VirtualKeyCode::Left as usize] && !now_keys[VirtualKeyCode::Right as usize] {
-            if state.vx > 0.1 {
-                state.ax = -0.1
-            } else if state.vx < -0.1 {
-                state.ax = 0.1
+            if self.vx > 0.1 {
+                self.ax = -0.1
+            } else if self.vx < -0.1 {
+                self.ax = 0.1
             } else {
-                state.ax = 0.0
+                self.ax = 0.0
             }
         }
 
         if now_keys[VirtualKeyCode::Up as usize]
             && prev_keys[VirtualKeyCode::Up as usize]
-            && state.shooting_timeout == 0
+            && self.shooting_timeout == 0
         {
-            state.shooting_timeout = 20;
-            state.player_bullets.push(Rect {
+            self.shooting_timeout = 20;
+            let bullet = Rect {
                 pos: Vec2i {
-                    x: state.player_sprite.shape.pos.x + state.player_sprite.shape.sz.x / 2 - 1,
-                    y: state.player_sprite.shape.pos.y,
+                    x: self.player_sprite.shape.pos.x + self.player_sprite.shape.sz.x / 2 - 1,
+                    y: self.player_sprite.shape.pos.y,
                 },
                 sz: Vec2i { x: 2, y: 8 },
-            })
+            };
+            self.add_bullet(bullet, true);
         }
+        SceneChange::None
     }
 
-    fn render(state: &mut State, assets: &mut Assets, fb2d: &mut Image) {
-        if state.game_over != 0 {
+    fn render(&mut self, assets: &mut Assets, fb2d: &mut Image) {
+        if self.game_over {
             return;
         }
 
         fb2d.clear((0, 0, 0, 255));
 
         // PLAYER MOVEMENT
-        state.vx += state.ax;
-        state.player_sprite.shape.move_by(state.vx as i32, 0);
+        self.vx += self.ax;
+        self.player_sprite.shape.move_by(self.vx as i32, 0);
 
         // PLAYER BOUNDS CHECK
-        if state.player_sprite.shape.pos.x < 0 {
-            state.player_sprite.shape.pos.x = 0
+        if self.player_sprite.shape.pos.x < 0 {
+            self.player_sprite.shape.pos.x = 0
         }
-        if state.player_sprite.shape.pos.x > WIDTH - state.player_sprite.shape.sz.x {
-            state.player_sprite.shape.pos.x = WIDTH - state.player_sprite.shape.sz.x
+        if self.player_sprite.shape.pos.x > WIDTH - self.player_sprite.shape.sz.x {
+            self.player_sprite.shape.pos.x = WIDTH - self.player_sprite.shape.sz.x
         }
 
-        // UPDATE PLAYER BULLETS
-        if state.shooting_timeout > 0 {
-            state.shooting_timeout -= 1
-        }
+        self.world
+            .update(Thing::Player, self.player_sprite.frame_rect());
 
-        state.player_bullets.retain(|b| b.pos.y + b.sz.y > 0);
-
-        for bullet in state.player_bullets.iter_mut() {
-            bullet.pos.y -= 2 * BULLET_VELO;
-            fb2d.draw_rect(bullet, BLUE);
-        }
-
-        // UPDATE ENEMY BULLETS
-        state.enemy_bullets.retain(|b| b.pos.y < HEIGHT);
-
-        for bullet in state.enemy_bullets.iter_mut() {
-            bullet.pos.y += BULLET_VELO;
-            fb2d.draw_rect(bullet, RED);
+        // SHOOTING COOLDOWN
+        if self.shooting_timeout > 0 {
+            self.shooting_timeout -= 1
         }
 
         // UPDATE PLAYER
-        if state.game_over == 0 {
-            fb2d.bitblt(
-                &assets.spritesheet,
-                SS_PLAYER,
-                state.player_sprite.shape.pos,
-                false,
-            );
+        if !self.game_over {
+            self.draw_list.sprite(&self.player_sprite);
         }
 
         // UPDATE ENEMIES
-        let left = state.enemies[0].rect.pos.x;
-        let right = state.enemies.last().unwrap().rect.pos.x + 16;
+        // The fleet turns around when its outermost enemy reaches an edge
+        let (left, right) = {
+            let rects = self.entities.storage::<Rect>();
+            let enemies = self.entities.storage::<Enemy>();
+            enemies
+                .join(&rects)
+                .fold((WIDTH, 0), |(l, r), (_, _, rect)| {
+                    (l.min(rect.pos.x), r.max(rect.pos.x + rect.sz.x))
+                })
+        };
         if left <= 16 || right >= WIDTH - 16 {
-            state.evx *= -1
+            self.evx *= -1;
+            let enemies = self.entities.storage::<Enemy>();
+            let mut velocities = self.entities.storage_mut::<Velocity>();
+            for (enemy, _) in enemies.iter() {
+                velocities.insert(enemy, Velocity(Vec2i { x: self.evx, y: 0 }));
+            }
         }
 
-        let mut rng = rand::thread_rng();
+        // Everything moves, then bullets off the screen go away
+        ecs::movement(&self.entities);
+        let gone: Vec<Entity> = {
+            let rects = self.entities.storage::<Rect>();
+            let bullets = self.entities.storage::<Bullet>();
+            bullets
+                .join(&rects)
+                .filter(|(_, b, rect)| {
+                    if b.from_player {
+                        rect.bottom() <= 0
+                    } else {
+                        rect.pos.y >= HEIGHT
+                    }
+                })
+                .map(|(bullet, _, _)| bullet)
+                .collect()
+        };
+        for bullet in gone {
+            self.entities.despawn(bullet);
+        }
+        self.sweep();
 
-        let mut enemies_left = false;
-        for enemy in state.enemies.iter_mut() {
-            if enemy.alive {
-                enemies_left = true;
-            }
+        let mut rng = rand::thread_rng();
 
-            let mut dead_bullets = vec![];
+        let shots: Vec<Rect> = {
+            let rects = self.entities.storage::<Rect>();
+            let enemies = self.entities.storage::<Enemy>();
+            enemies
+                .join(&rects)
+                .filter(|_| rng.gen_range(0..400) == 0)
+                .map(|(_, _, rect)| Enemy::shoot(*rect))
+                .collect()
+        };
+        for shot in shots {
+            self.add_bullet(shot, false);
+        }
 
-            for (i, player_bullet) in state.player_bullets.iter().enumerate() {
-                if enemy.rect.contains_point(player_bullet.pos) && enemy.alive {
+        // COLLISIONS
+        ecs::collision(&self.entities, &mut self.world, Thing::Entity);
+        for event in self.world.tick() {
+            // Bullets are the only things that care what they touch, so they come first
+            let (bullet, other) = match event {
+                OverlapEvent::Begin(Thing::Entity(bullet), other) => (bullet, other),
+                _ => continue,
+            };
+            // A bullet that already hit something this frame is gone
+            if !self.entities.is_alive(bullet) {
+                continue;
+            }
+            match other {
+                Thing::Entity(enemy)
+                    if self.entities.is_alive(enemy) && self.entities.has::<Enemy>(enemy) =>
+                {
                     // play death animation
                     let speedup_factor = 12;
-                    enemy
-                        .sprite
-                        .set_animation(assets.enemy1_animation_set.play_animation(Action::Die));
-                    fb2d.bitblt(
-                        &assets.spritesheet,
-                        enemy.sprite.play_animation(&speedup_factor),
-                        enemy.rect.pos,
-                        false,
-                    );
-                    enemy.alive = false;
-                    dead_bullets.push(i);
+                    let pos = self.entities.get::<Rect>(enemy).unwrap().pos;
+                    let mut sprite = self.entities.get_mut::<Sprite>(enemy).unwrap();
+                    sprite.set_animation(assets.enemy1_animation_set.play_animation(Action::Die));
+                    sprite.shape.pos = pos;
+                    sprite.tick_animation(&speedup_factor);
+                    self.draw_list.sprite(&sprite);
+                    drop(sprite);
+                    self.entities.despawn(enemy);
+                    self.entities.despawn(bullet);
                 }
-            }
-
-            for i in dead_bullets {
-                state.player_bullets.remove(i);
-            }
-
-            enemy.rect.move_by(state.evx, 0);
-
-            if rng.gen_range(0..400) == 0 && enemy.alive {
-                state.enemy_bullets.push(enemy.shoot());
-            }
-
-            if enemy.alive {
-                let speedup_factor = 7;
-                fb2d.bitblt(
-                    &assets.spritesheet,
-                    enemy.sprite.play_animation(&speedup_factor),
-                    enemy.rect.pos,
-                    false,
-                );
+                Thing::Blocker(cell) if self.world.contains(other) => {
+                    // Knock out the blocker tile the bullet hit
+                    self.blockers.set_tile(cell, TileID::EMPTY);
+                    self.world.remove(other);
+                    self.entities.despawn(bullet);
+                }
+                _ => {}
             }
         }
+        self.sweep();
 
-        if !enemies_left {
-            if state.game_over == 0 {
-                state.game_over = 2;
-                fb2d.bitblt(
-                    &assets.winlose_spritesheet,
-                    SS_WIN,
-                    Vec2i {
-                        x: WIDTH / 2 - SS_WIN.sz.x / 2,
-                        y: HEIGHT / 2 - SS_WIN.sz.y / 2,
-                    },
-                    false,
-                );
-            }
+        // ENEMY BULLET & PLAYER COLLISION
+        // Being in the ship's box only means a bullet is close; it has to touch the ship
+        let hit = self
+            .world
+            .touching(Thing::Player)
+            .into_iter()
+            // Only enemy bullets care about the player
+            .filter(|thing| matches!(thing, Thing::Entity(_)))
+            .filter_map(|thing| self.world.rect(thing))
+            .any(|bullet| {
+                assets
+                    .player_masks
+                    .sprite_hits_rect(&self.player_sprite, bullet)
+            });
+        if hit && !self.game_over {
+            self.game_over = true;
+            let speedup_factor = 7;
+            self.player_sprite
+                .set_animation(assets.player_animation_set.play_animation(Action::Die));
+            self.player_sprite.tick_animation(&speedup_factor);
+            self.draw_list.sprite(&self.player_sprite);
+            self.draw_list.blit(
+                draw::HUD,
+                &assets.winlose_spritesheet,
+                SS_LOSE,
+                Vec2i {
+                    x: WIDTH / 2 - SS_LOSE.sz.x / 2,
+                    y: HEIGHT / 2 - SS_WIN.sz.y / 2,
+                },
+                false,
+            );
         }
 
-        // UPDATE BLOCKERS
-        for blocker in state.blockers.iter_mut() {
-            let mut dead_player_bullets = vec![];
-
-            for (i, player_bullet) in state.player_bullets.iter().enumerate() {
-                if blocker.rect.contains_point(player_bullet.pos) && blocker.alive {
-                    // play death animation
-                    blocker.alive = false;
-                    dead_player_bullets.push(i);
-                }
-            }
-
-            for i in dead_player_bullets {
-                state.player_bullets.remove(i);
-            }
-
-            let mut dead_enemy_bullets = vec![];
-
-            for (i, enemy_bullet) in state.enemy_bullets.iter().enumerate() {
-                if blocker.rect.contains_point(enemy_bullet.pos) && blocker.alive {
-                    // play death animation
-                    blocker.alive = false;
-                    dead_enemy_bullets.push(i);
-                }
-            }
+        ecs::animation(&self.entities, 7);
+        ecs::render(&self.entities, &mut self.draw_list);
 
-            for i in dead_enemy_bullets {
-                state.enemy_bullets.remove(i);
-            }
+        let enemies_left = self.entities.storage::<Enemy>().iter().next().is_some();
 
-            if blocker.alive {
-                fb2d.draw_rect(&blocker.rect, BLUE);
-            }
+        if !enemies_left && !self.game_over {
+            self.game_over = true;
+            self.draw_list.blit(
+                draw::HUD,
+                &assets.winlose_spritesheet,
+                SS_WIN,
+                Vec2i {
+                    x: WIDTH / 2 - SS_WIN.sz.x / 2,
+                    y: HEIGHT / 2 - SS_WIN.sz.y / 2,
+                },
+                false,
+            );
         }
 
-        // ENEMY BULLET & PLAYER COLLISION
-        for enemy_bullet in state.enemy_bullets.iter() {
-            let mut hitbox = state.player_sprite.shape;
-            hitbox.pos.x += 5;
-            hitbox.sz.x = 22;
-            if hitbox.contains_point(enemy_bullet.pos) {
-                if state.game_over == 0 {
-                    state.game_over = 1;
-                    let speedup_factor = 7;
-                    state
-                        .player_sprite
-                        .set_animation(assets.player_animation_set.play_animation(Action::Die));
-                    fb2d.bitblt(
-                        &assets.spritesheet,
-                        state.player_sprite.play_animation(&speedup_factor),
-                        state.player_sprite.shape.pos,
-                        false,
-                    );
-                    fb2d.bitblt(
-                        &assets.winlose_spritesheet,
-                        SS_LOSE,
-                        Vec2i {
-                            x: WIDTH / 2 - SS_LOSE.sz.x / 2,
-                            y: HEIGHT / 2 - SS_WIN.sz.y / 2,
-                        },
-                        false,
-                    );
-                }
-            }
-        }
+        // UPDATE BLOCKERS
+        self.blockers.queue_draw(&mut self.draw_list, &self.camera);
+
+        self.draw_list.flush(fb2d);
     }
 }